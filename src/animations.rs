@@ -3,7 +3,7 @@ use bevy::core::FrameCount;
 use crate::bug::{BugSprite};
 use crate::level::LevelManager;
 use crate::selection::{SelectionEvent, TileSelection};
-use crate::tilemap::TileType;
+use crate::tilemap::{TileLayer, TileType};
 use crate::tower::TowerType;
 
 #[derive(Resource)]
@@ -33,11 +33,11 @@ pub fn config_selection_animation(
         // println!("[DEBUG] WOOOOOOOOOOOOOOOOOO");
         if let Some(selected) = event.selected {
             // println!("[DEBUG] s");
-            level.tilemap.set(&mut commands, selected, Some(TileType::SelectionBigger));
+            level.tilemap.set(&mut commands, TileLayer::Selection, selected, Some(TileType::SelectionBigger));
         }
         if let Some(deselected) = event.deselected {
             // println!("[DEBUG] d");
-            level.tilemap.set(&mut commands, deselected, None);
+            level.tilemap.set(&mut commands, TileLayer::Selection, deselected, None);
         }
         // timer.0.pause();
         // timer.0.reset();
@@ -57,7 +57,7 @@ pub fn selection_animation(
     if timer.0.tick(time.delta()).just_finished() {
         for tile in tiles {
             for event in selection_event_reader.read() {
-                level.tilemap.set(&mut commands, event.selected.unwrap(), Some(tile));
+                level.tilemap.set(&mut commands, TileLayer::Selection, event.selected.unwrap(), Some(tile));
             }
         }
     }
@@ -69,12 +69,12 @@ pub fn led_tower_animation(
 ) {
     let mut level = manager.get_current_level_mut();
     for (position, tower) in level.towers.iter() {
-        if tower.tower_type == TowerType::Diode {
+        if tower.tower_type == TowerType::Diode && !tower.is_disabled() {
             if tower.frame_counter == 0 {
-                 level.tilemap.set(&mut commands, IVec3::new(position.0, position.1, 4), Some(TileType::LedOn));
+                 level.tilemap.set(&mut commands, TileLayer::Tower, *position, Some(TileType::LedOn));
             }
             if tower.frame_counter == DIODE_DURATION {
-                level.tilemap.set(&mut commands, IVec3::new(position.0, position.1, 4), Some(TileType::LedOff));
+                level.tilemap.set(&mut commands, TileLayer::Tower, *position, Some(TileType::LedOff));
             }
         }
     }
@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use crate::bug::BugType;
+use crate::camera::CameraTransition;
+use crate::command::PlayerCommand;
+use crate::level::{Level, LevelManager};
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::replay::replaying;
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH};
+use crate::tower::{tile_to_tower_types, Balance, TowerType, CAPACITOR_RANGE, LED_RANGE};
+use crate::wave::{get_wave_composition, GameState};
+
+#[derive(Clone)]
+pub struct AutoplayStrategy {
+    // the root level is not continued past this round
+    pub rounds: u32,
+    // money every level keeps aside instead of spending it
+    pub reserve: i32,
+    // a tower of each type is worth its cost divided by the weight, the best deal gets built first
+    pub led_weight: f32,
+    pub capacitor_weight: f32,
+    pub resistor_weight: f32,
+    // towers are kept at an upgrade factor of 1 + round / upgrade_every of their level, 0 never steps in
+    pub upgrade_every: u32,
+    // towers get donations until their level holds this much
+    pub donate: i32,
+    // how many levels deep the bot goes to upgrade towers
+    pub max_depth: usize,
+    // ticks between two commands, so a human can follow along in the window
+    pub think_ticks: u32
+}
+
+impl Default for AutoplayStrategy {
+    fn default() -> Self {
+        Self {
+            rounds: 10,
+            reserve: 0,
+            led_weight: 1.0,
+            capacitor_weight: 1.0,
+            resistor_weight: 0.5,
+            upgrade_every: 1,
+            donate: 0,
+            max_depth: 1,
+            think_ticks: 0
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Autoplay {
+    pub enabled: bool,
+    pub strategy: AutoplayStrategy,
+    pub cooldown: u32
+}
+
+impl Autoplay {
+    pub fn new(enabled: bool, strategy: AutoplayStrategy) -> Self {
+        Self { enabled, strategy, cooldown: 0 }
+    }
+}
+
+fn wanted_upgrade(level: &Level, strategy: &AutoplayStrategy) -> u32 {
+    if strategy.upgrade_every == 0 { return 1; }
+    1 + level.round / strategy.upgrade_every
+}
+
+// how much of the cable a tower on this tile would cover, tiles near the end count more
+// since bugs there are the ones about to leak
+fn tile_score(level: &Level, tower_type: &TowerType, position: (i32, i32)) -> f32 {
+    let length = level.cable.len() as f32;
+    match tower_type {
+        TowerType::Resistor => {
+            // slowing bugs down pays off where the LEDs can hit them longer
+            let covered = level.towers.iter()
+                .filter(|(_, tower)| tower.tower_type == TowerType::Diode)
+                .filter(|(tower_position, _)| distance(**tower_position, position) <= LED_RANGE)
+                .count() as f32;
+            let progress = level.cable.iter().position(|tile| *tile == position).unwrap_or(0) as f32;
+            covered + progress / length
+        },
+        _ => {
+            let range = if *tower_type == TowerType::Diode { LED_RANGE } else { CAPACITOR_RANGE };
+            level.cable.iter().enumerate()
+                .filter(|(_, tile)| distance(**tile, position) <= range)
+                .map(|(index, _)| 1.0 + index as f32 / length)
+                .sum()
+        }
+    }
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> f32 {
+    Vec2::new((a.0 - b.0) as f32, (a.1 - b.1) as f32).length()
+}
+
+fn plan_build(level: &Level, balance: &Balance, strategy: &AutoplayStrategy) -> Option<PlayerCommand> {
+    let (next_wave, _) = get_wave_composition(level.round + 1);
+    let hamsters = next_wave.iter().any(|bug| matches!(bug, BugType::Hamster));
+    let mut choices = vec![
+        (TowerType::Diode, strategy.led_weight),
+        // a single tanky bug is what the capacitor's big shots are for
+        (TowerType::Capacitor, if hamsters { strategy.capacitor_weight * 2.0 } else { strategy.capacitor_weight }),
+        (TowerType::Resistor, strategy.resistor_weight)
+    ];
+    choices.retain(|(_, weight)| *weight > 0.0);
+    choices.sort_by(|a, b| (level.cost(&a.0, balance) as f32 / a.1).total_cmp(&(level.cost(&b.0, balance) as f32 / b.1)));
+
+    for (tower_type, _) in choices {
+        if level.money - (level.cost(&tower_type, balance) as i32) < strategy.reserve { continue; }
+        let mut best: Option<((i32, i32), f32)> = None;
+        for x in -MAP_WIDTH / 2..MAP_WIDTH / 2 {
+            for y in -MAP_HEIGHT / 2..MAP_HEIGHT / 2 {
+                if !tile_to_tower_types(&level.tilemap, (x, y)).contains(&tower_type) { continue; }
+                let score = tile_score(level, &tower_type, (x, y));
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some(((x, y), score));
+                }
+            }
+        }
+        if let Some((position, score)) = best {
+            if score > 0.0 {
+                return Some(PlayerCommand::Build {tower: tower_type, position});
+            }
+        }
+    }
+    None
+}
+
+// the next thing to do on the active level, one command at a time so each one sees the result of the last
+pub fn next_command(manager: &LevelManager, state: &GameState, balance: &Balance, strategy: &AutoplayStrategy) -> Option<PlayerCommand> {
+    if state.round_running || state.health <= 0 { return None; }
+    let level = manager.get_current_level();
+
+    if let Some(command) = plan_build(level, balance, strategy) {
+        return Some(command);
+    }
+
+    let towers: Vec<_> = level.towers.iter().collect();
+    if strategy.donate > 0 && level.money - 100 >= strategy.reserve {
+        if let Some((position, _)) = towers.iter().find(|(_, tower)| tower.balance < strategy.donate) {
+            return Some(PlayerCommand::Donate {position: **position});
+        }
+    }
+
+    if manager.depth(manager.active) < strategy.max_depth {
+        let wanted = wanted_upgrade(level, strategy);
+        if let Some((position, _)) = towers.iter().find(|(_, tower)| tower.upgrade_factor < wanted) {
+            return Some(PlayerCommand::StepInto {position: **position});
+        }
+    }
+
+    match level.parent {
+        // nested levels are only played until the tower they live in is upgraded enough
+        Some(parent) => {
+            let wanted = wanted_upgrade(&manager.levels[parent], strategy);
+            match manager.owning_tower(manager.active) {
+                Some(tower) if tower.upgrade_factor < wanted => Some(PlayerCommand::StartWave),
+                _ => Some(PlayerCommand::StepOut)
+            }
+        },
+        None if level.round < strategy.rounds => Some(PlayerCommand::StartWave),
+        None => None
+    }
+}
+
+pub fn autoplay(
+    mut autoplay: ResMut<Autoplay>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    balance: Res<Balance>,
+    transition: Option<Res<CameraTransition>>,
+    mut command_writer: EventWriter<PlayerCommand>
+) {
+    if !autoplay.enabled { return; }
+    if transition.is_some_and(|transition| transition.0.is_some()) { return; }
+    if autoplay.cooldown > 0 {
+        autoplay.cooldown -= 1;
+        return;
+    }
+    let Some(command) = next_command(&manager, &state, &balance, &autoplay.strategy) else { return };
+    command_writer.send(command);
+    autoplay.cooldown = autoplay.strategy.think_ticks;
+}
+
+pub fn toggle_autoplay(keys: Res<ButtonInput<KeyCode>>, mut autoplay: ResMut<Autoplay>) {
+    if keys.just_pressed(KeyCode::F7) {
+        autoplay.enabled = !autoplay.enabled;
+        info!("autoplay {}", if autoplay.enabled { "on" } else { "off" });
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AutoplaySet;
+
+// off until F7 is pressed, unless an Autoplay resource was inserted before the plugin
+pub struct AutoplayPlugin;
+
+impl Plugin for AutoplayPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        if !app.world.contains_resource::<Autoplay>() {
+            app.insert_resource(Autoplay::new(false, AutoplayStrategy {think_ticks: 30, ..default()}));
+        }
+        app.add_event::<PlayerCommand>()
+            .add_systems(Update, (
+                toggle_autoplay.run_if(resource_exists::<ButtonInput<KeyCode>>),
+                autoplay.after(toggle_autoplay).run_if(not(replaying)),
+            ).in_set(AutoplaySet).in_set(GameSet::Input));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+use step_into::scenario::{run_scenario, Scenario, Sweep};
+use step_into::tower::Balance;
+
+const USAGE: &str = "usage: step-into-sim SCENARIO [options]
+  --sweep PARAMETER=FROM:TO:STEP   rerun the scenario for every value, e.g. capacitor_cost=50:150:25
+  --verbose                        print the round table of every run in a sweep
+  --help                           show this message";
+
+fn fail(message: String) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(1);
+}
+
+fn main() {
+    let mut path = None;
+    let mut sweep = None;
+    let mut verbose = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sweep" => {
+                let spec = args.next().unwrap_or_else(|| fail("--sweep needs a value".to_string()));
+                sweep = Some(Sweep::parse(&spec).unwrap_or_else(|error| fail(error)));
+            },
+            "--verbose" => verbose = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with("--") => fail(format!("unknown option {}", arg)),
+            _ => path = Some(arg)
+        }
+    }
+    let path = path.unwrap_or_else(|| fail("no scenario given".to_string()));
+    let scenario = Scenario::read(&path).unwrap_or_else(|error| fail(format!("could not read scenario {}: {}", path, error)));
+
+    // the command line wins over a sweep written into the scenario
+    let Some(sweep) = sweep.or(scenario.sweep.clone()) else {
+        print!("{}", run_scenario(&scenario, Balance::default()));
+        return;
+    };
+    let values = sweep.values().unwrap_or_else(|error| fail(error));
+    let name = sweep.parameter.name();
+    let mut summary = format!("{:>width$}  survived     hp  money  kills  leaks\n", name, width = name.len().max(6));
+    for value in values {
+        let mut balance = Balance::default();
+        sweep.parameter.apply(&mut balance, value);
+        let report = run_scenario(&scenario, balance);
+        if verbose {
+            println!("{} = {}\n{}", name, value, report);
+        }
+        let health = report.rounds.last().map_or(scenario.health, |round| round.health);
+        let money = report.rounds.last().map_or(scenario.money, |round| round.money);
+        summary += &format!("{:>width$}  {:>5}/{:<2} {:>6} {:>6} {:>6} {:>6}\n",
+            value, report.rounds_survived(), scenario.rounds, health, money, report.total_kills(), report.total_leaks(), width = name.len().max(6));
+    }
+    print!("{}", summary);
+}
\ No newline at end of file
@@ -3,121 +3,113 @@ use bevy::input::ButtonInput;
 use bevy::prelude::*;
 use bevy::sprite::SpriteBundle;
 use bevy::audio::*;
+use serde::{Deserialize, Serialize};
 use crate::level::{Level, LevelManager};
 use crate::cable::{delta, Direction};
-use crate::wave::GameState;
 use crate::sounds::{BugDeathSound};
-use crate::tilemap::{TilemapFactory, TILE_SIZE};
+use crate::tilemap::TILE_SIZE;
+use crate::animations::{bugs_animation, BugsAnimationTimer};
+use crate::spatial::{index_bugs, BugIndex};
+use crate::tower::handle_capacitor_bullet;
+use crate::wave::{end_wave, spawn_wave, GameState};
+use crate::pipeline::{configure_pipeline, once_per_frame, GameSet};
 
 const BUG_SPEED: f32 = 2.137;
+const LEAK_DAMAGE: i32 = 10;
+const HAMSTER_LEAK_DAMAGE: i32 = 2137;
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct BugSprite {
     pub cable_progress: usize,
     pub resistor_debuff: f32,
     pub health: i32,
     pub speed_factor: f32,
-    pub hamster: bool
+    pub bug_type: BugType,
+    pub last_hit: Option<(i32, i32)>
 }
 
-#[derive(Resource)]
-pub struct BugFactory {
+// sent when a bug is shot down, tower is the one that landed the last hit
+#[derive(Event)]
+pub struct BugKilledEvent {
+    pub bug_type: BugType,
+    pub position: (i32, i32),
+    pub tower: Option<(i32, i32)>
+}
+
+#[derive(Event)]
+pub struct BugLeakedEvent {
+    pub bug_type: BugType,
+    pub damage: i32
+}
+
+pub struct BugTextures {
     pub(crate) texture: [Handle<Image>; 9],
     pub(crate) atlas_layout: Handle<TextureAtlasLayout>,
 }
 
-#[derive(Clone)]
+// bugs spawned without textures only carry a Transform and their BugSprite
+#[derive(Resource)]
+pub struct BugFactory {
+    pub(crate) textures: Option<BugTextures>
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum BugType {
     Bug,
     Ant,
     Hamster
 }
 
+impl BugType {
+    pub fn stats(&self) -> BugSprite {
+        let (health, speed_factor) = match self {
+            BugType::Bug => (1000, 1.0),
+            BugType::Ant => (500, 1.7),
+            BugType::Hamster => (15000, 0.4)
+        };
+        BugSprite {
+            cable_progress: 0,
+            resistor_debuff: 1.0,
+            health,
+            speed_factor,
+            bug_type: self.clone(),
+            last_hit: None
+        }
+    }
+}
+
 impl BugFactory {
-    pub fn instantiate_bugs(&self, transform: Transform) -> (SpriteBundle, TextureAtlas, BugSprite){
-        let duration_since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let random = ((duration_since_epoch.as_nanos() * duration_since_epoch.as_nanos()) as usize) / 10;
-        let seed = random % 4;
-        let textures = self.texture.clone();
-        (
-            SpriteBundle {
-                texture: textures[seed].clone(),
-                transform,
-                sprite: Sprite {
-                    custom_size: Some(Vec2::splat(TILE_SIZE as f32) * 0.8),
-                    ..default()
-                },
-                ..default()
-            },
-            TextureAtlas {
-                layout: self.atlas_layout.clone(),
-                index: 0
-            },
-            BugSprite {
-                cable_progress: 0,
-                resistor_debuff: 1.0,
-                health: 1000,
-                speed_factor: 1.0,
-                hamster: false
-            }
-        )
+    pub fn headless() -> Self {
+        Self { textures: None }
     }
 
-    pub fn instantiate_ants(&self, transform: Transform) -> (SpriteBundle, TextureAtlas, BugSprite){
+    pub fn spawn(&self, commands: &mut Commands, bug_type: &BugType, transform: Transform) -> Entity {
+        let Some(textures) = &self.textures else {
+            return commands.spawn((transform, bug_type.stats())).id();
+        };
         let duration_since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let random = ((duration_since_epoch.as_nanos() * duration_since_epoch.as_nanos()) as usize) / 10;
-        let seed = (random % 4) + 4;
-        let textures = self.texture.clone();
-        (
+        let (texture, size) = match bug_type {
+            BugType::Bug => (textures.texture[random % 4].clone(), 0.8),
+            BugType::Ant => (textures.texture[(random % 4) + 4].clone(), 0.8),
+            BugType::Hamster => (textures.texture[8].clone(), 2.0)
+        };
+        commands.spawn((
             SpriteBundle {
-                texture: textures[seed].clone(),
+                texture,
                 transform,
                 sprite: Sprite {
-                    custom_size: Some(Vec2::splat(TILE_SIZE as f32) * 0.8),
+                    custom_size: Some(Vec2::splat(TILE_SIZE as f32) * size),
                     ..default()
                 },
                 ..default()
             },
             TextureAtlas {
-                layout: self.atlas_layout.clone(),
+                layout: textures.atlas_layout.clone(),
                 index: 0
             },
-            BugSprite {
-                cable_progress: 0,
-                resistor_debuff: 1.0,
-                health: 500,
-                speed_factor: 1.7,
-                hamster: false
-            }
-        )
-    }
-    pub fn instantiate_hamster(&self, transform: Transform) -> (SpriteBundle, TextureAtlas, BugSprite){
-        // let duration_since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        // let random = ((duration_since_epoch.as_nanos() * duration_since_epoch.as_nanos()) as usize) / 10;
-        // let seed = (random % 4) + 4;
-        let texture = self.texture[8].clone();
-        (
-            SpriteBundle {
-                texture: texture.clone(),
-                transform,
-                sprite: Sprite {
-                    custom_size: Some(Vec2::splat(TILE_SIZE as f32) * 2.0),
-                    ..default()
-                },
-                ..default()
-            },
-            TextureAtlas {
-                layout: self.atlas_layout.clone(),
-                index: 0
-            },
-            BugSprite {
-                cable_progress: 0,
-                resistor_debuff: 1.0,
-                health: 15000,
-                speed_factor: 0.4,
-                hamster: true
-            }
-        )
+            bug_type.stats()
+        )).id()
     }
 }
 
@@ -134,8 +126,10 @@ pub fn load_bugs(mut commands: Commands, mut texture_atlases: ResMut<Assets<Text
         assets.load("sprites/Hamster-sprite-final.png"),
     ];
     let bug_factory = BugFactory {
-        atlas_layout: texture_atlases.add(TextureAtlasLayout::from_grid(Vec2::splat(16.0), 4, 1, None, None)),
-        texture: textures,
+        textures: Some(BugTextures {
+            atlas_layout: texture_atlases.add(TextureAtlasLayout::from_grid(Vec2::splat(16.0), 4, 1, None, None)),
+            texture: textures,
+        })
     };
     commands.insert_resource(bug_factory)
 }
@@ -146,17 +140,18 @@ pub fn debug_spawn_bug(mut commands: Commands, mut manager: ResMut<LevelManager>
         let position = level.tilemap.grid_to_translation(level.cable[0]);
         let translation = Vec3::from((position, 2.0));
         // let translation = Vec3::from((0.0, 0.0, 2.0));
-        let bug = bug_factory.instantiate_ants(Transform::from_translation(translation));
-        commands.spawn(bug);
+        bug_factory.spawn(&mut commands, &BugType::Ant, Transform::from_translation(translation));
     }
 }
 
 pub fn move_bugs(
     mut commands: Commands,
     mut bugs_query: Query<(Entity, &mut Transform, &mut BugSprite)>,
-    manager: Res<LevelManager>,
-    mut state: ResMut<GameState>
+    mut manager: ResMut<LevelManager>,
+    mut state: ResMut<GameState>,
+    mut leaked_writer: EventWriter<BugLeakedEvent>
 ) {
+    let mut leaked = 0;
     let level = manager.get_current_level();
     for (entity, mut transform, mut bug_sprite) in bugs_query.iter_mut() {
         let focus_tile = level.cable[bug_sprite.cable_progress];
@@ -166,10 +161,9 @@ pub fn move_bugs(
             bug_sprite.cable_progress += 1;
             if bug_sprite.cable_progress >= level.cable.len() {
                 commands.entity(entity).despawn();
-                state.health -= 10;
-                if bug_sprite.hamster {
-                    state.health -= 2137;
-                }
+                let damage = if matches!(bug_sprite.bug_type, BugType::Hamster) { LEAK_DAMAGE + HAMSTER_LEAK_DAMAGE } else { LEAK_DAMAGE };
+                leaked += damage;
+                leaked_writer.send(BugLeakedEvent {bug_type: bug_sprite.bug_type.clone(), damage});
                 continue;
             }
             continue;
@@ -183,16 +177,34 @@ pub fn move_bugs(
         // println!("[DEBUG] angle: {:?}", angle);
         transform.rotation = Quat::from_rotation_z(angle - std::f32::consts::FRAC_PI_2);
     }
+    if leaked == 0 { return; }
+
+    // leaks in a nested level wear down the tower it lives in, only the root level costs HP
+    let active = manager.active;
+    if let Some(tower) = manager.owning_tower_mut(active) {
+        tower.durability = (tower.durability - leaked).max(0);
+    } else {
+        state.health -= leaked;
+    }
 }
 
 pub fn check_bug_health(
     mut commands: Commands,
-    mut bugs_query: Query<(Entity, &mut BugSprite)>,
-    asset_server: Res<AssetServer>
+    mut bugs_query: Query<(Entity, &Transform, &mut BugSprite)>,
+    mut manager: ResMut<LevelManager>,
+    asset_server: Option<Res<AssetServer>>,
+    mut killed_writer: EventWriter<BugKilledEvent>
 ) {
-    for (entity, mut bug_sprite) in bugs_query.iter_mut() {
+    for (entity, transform, mut bug_sprite) in bugs_query.iter_mut() {
         if bug_sprite.health <= 0 {
             // println!("[DEBUG] Despawning bug: {:?}", entity);
+            if let Some(tower) = bug_sprite.last_hit.and_then(|position| manager.get_current_level_mut().towers.get_mut(&position)) {
+                tower.kills += 1;
+            }
+            let position = manager.get_current_level().tilemap.translation_to_grid(transform.translation.truncate());
+            killed_writer.send(BugKilledEvent {bug_type: bug_sprite.bug_type.clone(), position, tower: bug_sprite.last_hit});
+            commands.entity(entity).despawn();
+            let Some(asset_server) = &asset_server else { continue };
             commands.spawn((
                 AudioBundle {
                     source: asset_server.load("sounds/bug_die.ogg"),
@@ -205,7 +217,30 @@ pub fn check_bug_health(
                     ..default()
                 },
             ));
-            commands.entity(entity).despawn();
         }
     }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BugSet;
+
+pub struct BugPlugin;
+
+impl Plugin for BugPlugin {
+    fn build(&self, app: &mut App) {
+        // replaced by the textured factory once load_bugs runs
+        configure_pipeline(app);
+        app.insert_resource(BugFactory::headless())
+            .insert_resource(BugIndex::default())
+            .insert_resource(BugsAnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
+            .add_event::<BugKilledEvent>()
+            .add_event::<BugLeakedEvent>()
+            .add_systems(Startup, load_bugs.run_if(resource_exists::<AssetServer>))
+            .add_systems(Update, (
+                move_bugs.after(spawn_wave),
+                index_bugs.after(move_bugs),
+                check_bug_health.after(handle_capacitor_bullet).before(end_wave),
+            ).in_set(BugSet).in_set(GameSet::Simulation))
+            .add_systems(Update, bugs_animation.run_if(once_per_frame).in_set(BugSet).in_set(GameSet::Presentation));
+    }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::tilemap::{TileType, Tilemap};
+use crate::tilemap::{TileLayer, TileType, Tilemap};
 
 pub fn random_path(seed: usize) -> Vec<(i32, i32)> {
     let paths = [
@@ -11,7 +11,7 @@ pub fn random_path(seed: usize) -> Vec<(i32, i32)> {
 
     let mut seed = seed;
     for i in 1..100 {
-        seed = ((seed + i) * 23) % 100000
+        seed = (seed.wrapping_add(i).wrapping_mul(23)) % 100000
     }
     seed += 5;
     // println!("{}", seed);
@@ -83,13 +83,13 @@ pub fn set_cable(tilemap: &mut Tilemap, commands: &mut Commands, path: &Vec<(i32
             (Some(Direction::South), Some(Direction::West)) => TileType::SouthWestCable,
             (_, _) => {unreachable!()}
         };
-        let z= match tile_type {
-            TileType::EndNorthCable => 3,
-            TileType::EndSouthCable => 3,
-            TileType::EndWestCable => 3,
-            TileType::EndEastCable => 3,
-            _ => 1
+        let layer = match tile_type {
+            TileType::EndNorthCable => TileLayer::CableEnd,
+            TileType::EndSouthCable => TileLayer::CableEnd,
+            TileType::EndWestCable => TileLayer::CableEnd,
+            TileType::EndEastCable => TileLayer::CableEnd,
+            _ => TileLayer::Cable
         };
-        tilemap.set(commands, IVec3::new(coordinate.0, coordinate.1, z), Some(tile_type))
+        tilemap.set(commands, layer, *coordinate, Some(tile_type))
     }
 }
\ No newline at end of file
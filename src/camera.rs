@@ -1,6 +1,18 @@
 use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::window::PrimaryWindow;
+use crate::pipeline::{configure_pipeline, once_per_frame, GameSet};
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE};
+use crate::ui::MENU_WIDTH;
 
-pub const CAMERA_OFFSET: f32 = 184.0;
+// the map and the sidebar at scale 1.0, in world units
+pub const VIEW_WIDTH: f32 = (MAP_WIDTH * TILE_SIZE) as f32 + MENU_WIDTH;
+pub const VIEW_HEIGHT: f32 = (MAP_HEIGHT * TILE_SIZE) as f32;
+// tiles are 16px textures drawn TILE_SIZE wide
+const TEXEL_SIZE: f32 = TILE_SIZE as f32 / 16.0;
+
+// shifts the map left so it's centred in whatever the sidebar leaves free, at any window size
+pub const CAMERA_OFFSET: f32 = MENU_WIDTH / 2.0;
 pub fn setup_camera(mut commands: Commands) {
     let color = Color::BLACK;
     commands.spawn(
@@ -13,4 +25,189 @@ pub fn setup_camera(mut commands: Commands) {
             ..Default::default()
         }
     );
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new((MAP_WIDTH * TILE_SIZE) as f32, (MAP_HEIGHT * TILE_SIZE) as f32)),
+                color: Color::rgba(1., 1., 1., 0.),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        TransitionOverlay,
+    ));
+}
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 }
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct TransitionSettings {
+    pub duration: f32,
+    pub easing: Easing,
+    pub zoom: f32
+}
+
+impl Default for TransitionSettings {
+    fn default() -> Self {
+        Self { duration: 0.8, easing: Easing::EaseInOut, zoom: 0.1 }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CameraKeyframe {
+    pub translation: Vec3,
+    pub scale: f32
+}
+
+impl CameraKeyframe {
+    pub fn level(center: Vec3, scale: f32) -> Self {
+        Self { translation: center, scale }
+    }
+
+    // keeps the focused tile where the middle of the map is at scale 1.0
+    pub fn tile(tile: Vec2, scale: f32) -> Self {
+        Self { translation: Vec3::new(tile.x + CAMERA_OFFSET * scale, tile.y, 0.0), scale }
+    }
+
+    fn lerp(&self, other: &CameraKeyframe, t: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            translation: self.translation.lerp(other.translation, t),
+            scale: self.scale + (other.scale - self.scale) * t
+        }
+    }
+}
+
+// first half plays on the level being left while the next background fades in,
+// second half plays on the entered level while the overlay fades back out
+pub struct Transition {
+    timer: Timer,
+    leave: (CameraKeyframe, CameraKeyframe),
+    enter: (CameraKeyframe, CameraKeyframe),
+    background: Handle<Image>
+}
+
+#[derive(Resource)]
+pub struct CameraTransition(pub Option<Transition>);
+
+#[derive(Component)]
+pub struct TransitionOverlay;
+
+impl CameraTransition {
+    pub fn start(&mut self, settings: &TransitionSettings, leave: (CameraKeyframe, CameraKeyframe), enter: (CameraKeyframe, CameraKeyframe), background: Handle<Image>) {
+        self.0 = Some(Transition {
+            timer: Timer::from_seconds(settings.duration.max(0.001), TimerMode::Once),
+            leave,
+            enter,
+            background
+        });
+    }
+}
+
+pub fn transition_running(transition: Res<CameraTransition>) -> bool {
+    transition.0.is_some()
+}
+
+type OverlayQuery<'w, 's> = Query<'w, 's, (&'static mut Transform, &'static mut Sprite, &'static mut Handle<Image>, &'static mut Visibility), (With<TransitionOverlay>, Without<Camera>)>;
+
+pub fn camera_transition(
+    mut transition: ResMut<CameraTransition>,
+    settings: Res<TransitionSettings>,
+    time: Res<Time>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    mut overlay_query: OverlayQuery
+) {
+    let Some(running) = transition.0.as_mut() else { return };
+    let (mut camera_transform, mut projection) = camera_query.single_mut();
+    let (mut overlay_transform, mut overlay_sprite, mut overlay_texture, mut overlay_visibility) = overlay_query.single_mut();
+
+    let progress = running.timer.tick(time.delta()).fraction();
+    let (keyframe, fade) = if progress < 0.5 {
+        let t = settings.easing.apply(progress * 2.0);
+        (running.leave.0.lerp(&running.leave.1, t), t)
+    } else {
+        let t = settings.easing.apply(progress * 2.0 - 1.0);
+        (running.enter.0.lerp(&running.enter.1, t), 1.0 - t)
+    };
+
+    if running.timer.finished() {
+        let end = running.enter.1;
+        camera_transform.translation = end.translation;
+        projection.scale = end.scale;
+        *overlay_visibility = Visibility::Hidden;
+        transition.0 = None;
+        return;
+    }
+
+    camera_transform.translation = keyframe.translation;
+    projection.scale = keyframe.scale;
+
+    *overlay_texture = running.background.clone();
+    *overlay_visibility = Visibility::Visible;
+    overlay_sprite.color.set_a(fade);
+    overlay_transform.translation = Vec3::new(keyframe.translation.x - CAMERA_OFFSET * keyframe.scale, keyframe.translation.y, 100.0);
+    overlay_transform.scale = Vec3::new(keyframe.scale, keyframe.scale, 1.0);
+}
+
+// largest scale that fits the whole view, snapped so every texel covers a whole number of pixels
+// unless the window is too small for even one pixel per texel
+pub fn view_scale(width: f32, height: f32, scale_factor: f32) -> f32 {
+    let fit = (width / VIEW_WIDTH).min(height / VIEW_HEIGHT);
+    let texel = TEXEL_SIZE * scale_factor;
+    if fit * texel >= 1.0 { (fit * texel).floor() / texel } else { fit }
+}
+
+// the projection's scale belongs to the transitions, so the window fit goes into the scaling mode
+pub fn fit_to_window(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut OrthographicProjection, With<Camera>>,
+    mut ui_scale: ResMut<UiScale>
+) {
+    let Ok(window) = window_query.get_single() else { return };
+    // a minimised window has no size, the last fit stays until it comes back
+    if window.width() <= 0.0 || window.height() <= 0.0 { return; }
+    let Ok(mut projection) = camera_query.get_single_mut() else { return };
+    let scale = view_scale(window.width(), window.height(), window.scale_factor());
+    if !matches!(projection.scaling_mode, ScalingMode::WindowSize(current) if current == scale) {
+        projection.scaling_mode = ScalingMode::WindowSize(scale);
+    }
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CameraSet;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<TransitionSettings>()
+            .insert_resource(CameraTransition(None))
+            .add_systems(Startup, setup_camera)
+            .add_systems(Update, (
+                fit_to_window,
+                camera_transition.run_if(once_per_frame),
+            ).in_set(CameraSet).in_set(GameSet::Presentation));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,73 @@
+use crate::protocol::Transport;
+
+pub const USAGE: &str = "usage: step-into [options]
+  --seed N           seed for the root cable and every level built after it (default 2137)
+  --start-round N    first wave to play, the rounds before it count as won (default 1)
+  --money N          Bit$ on the root level (default 300)
+  --health N         starting HP (default 100)
+  --level FILE       start from a saved game instead of a new one
+  --fullscreen       open the window fullscreen
+  --headless         play without a window and print a report
+  --replay FILE      play a recorded game back
+  --protocol SPEC    take commands over stdio or tcp:PORT
+  --autoplay         let the built-in bot play
+  --event-log FILE   write every gameplay event to FILE, one JSON object per line
+  --help             show this message";
+
+#[derive(Default)]
+pub struct Options {
+    pub seed: Option<u64>,
+    pub start_round: Option<u32>,
+    pub money: Option<i32>,
+    pub health: Option<i32>,
+    pub level: Option<String>,
+    pub fullscreen: bool,
+    pub headless: bool,
+    pub replay: Option<String>,
+    pub protocol: Option<Transport>,
+    pub autoplay: bool,
+    pub event_log: Option<String>,
+    pub help: bool
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(number(&arg, args.next())?),
+                "--start-round" => {
+                    let round: u32 = number(&arg, args.next())?;
+                    if round == 0 { return Err("--start-round counts from 1".to_string()); }
+                    options.start_round = Some(round);
+                },
+                "--money" => options.money = Some(number(&arg, args.next())?),
+                "--health" => options.health = Some(number(&arg, args.next())?),
+                "--level" => options.level = Some(value(&arg, args.next())?),
+                "--fullscreen" => options.fullscreen = true,
+                "--headless" => options.headless = true,
+                "--replay" => options.replay = Some(value(&arg, args.next())?),
+                "--protocol" => options.protocol = Some(Transport::parse(&value(&arg, args.next())?)?),
+                "--autoplay" => options.autoplay = true,
+                "--event-log" => options.event_log = Some(value(&arg, args.next())?),
+                "--help" | "-h" => options.help = true,
+                _ => return Err(format!("unknown option {}", arg))
+            }
+        }
+        let sets_start = options.seed.is_some() || options.start_round.is_some() || options.money.is_some() || options.health.is_some() || options.level.is_some();
+        if options.replay.is_some() && sets_start {
+            return Err("--replay brings its own seed and starting state".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let text = self::value(flag, value)?;
+    text.parse().map_err(|_| format!("{} expects a number, got {}", flag, text))
+}
\ No newline at end of file
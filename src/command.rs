@@ -0,0 +1,184 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::bug::{BugSprite, BugType};
+use crate::level::{LevelManager, LevelTheme};
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::selection::{LevelSwitchEvent, TowerBuildEvent, TowerDeleteEvent, TowerDonateEvent};
+use crate::tower::{handle_build_tower, Balance, TowerType};
+use crate::wave::{GameState, WaveStateChange};
+
+// everything a player can do, whether it comes from the mouse, a replay or a bot.
+// positions are grid tiles on the active level
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum PlayerCommand {
+    Build { tower: TowerType, position: (i32, i32) },
+    Sell { position: (i32, i32) },
+    Donate { position: (i32, i32) },
+    StepInto { position: (i32, i32) },
+    StepOut,
+    SwitchLevel { index: usize },
+    StartWave,
+    QueryState
+}
+
+#[derive(SystemParam)]
+pub struct GameplayWriters<'w> {
+    tower_build_writer: EventWriter<'w, TowerBuildEvent>,
+    tower_delete_writer: EventWriter<'w, TowerDeleteEvent>,
+    tower_donate_writer: EventWriter<'w, TowerDonateEvent>,
+    level_switch_writer: EventWriter<'w, LevelSwitchEvent>,
+    wave_state_writer: EventWriter<'w, WaveStateChange>
+}
+
+// turns commands into the events the gameplay systems already understand, dropping the ones
+// the current state does not allow (levels can't be left while a wave is running)
+pub fn dispatch_player_commands(
+    mut command_reader: EventReader<PlayerCommand>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    mut writers: GameplayWriters
+) {
+    let level = manager.get_current_level();
+    let mut round_running = state.round_running;
+    for command in command_reader.read() {
+        match command {
+            PlayerCommand::Build {tower, position} => {
+                writers.tower_build_writer.send(TowerBuildEvent {tower: *tower, position: *position});
+            },
+            PlayerCommand::Sell {position} => if level.towers.contains_key(position) {
+                writers.tower_delete_writer.send(TowerDeleteEvent {position: *position});
+            },
+            PlayerCommand::Donate {position} => if level.towers.contains_key(position) {
+                writers.tower_donate_writer.send(TowerDonateEvent {position: *position});
+            },
+            PlayerCommand::StepInto {position} => if !round_running {
+                if let Some(tower) = level.towers.get(position) {
+                    writers.level_switch_writer.send(LevelSwitchEvent {index: tower.level_index});
+                }
+            },
+            PlayerCommand::StepOut => if !round_running {
+                if let Some(parent) = level.parent {
+                    writers.level_switch_writer.send(LevelSwitchEvent {index: parent});
+                }
+            },
+            PlayerCommand::SwitchLevel {index} => if !round_running && *index < manager.levels.len() && *index != manager.active {
+                writers.level_switch_writer.send(LevelSwitchEvent {index: *index});
+            },
+            PlayerCommand::StartWave => if !round_running {
+                writers.wave_state_writer.send(WaveStateChange {running: true});
+                round_running = true;
+            },
+            PlayerCommand::QueryState => {}
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TowerSnapshot {
+    pub tower_type: TowerType,
+    pub position: (i32, i32),
+    pub level_index: usize,
+    pub upgrade_factor: u32,
+    pub balance: i32,
+    pub durability: i32,
+    pub kills: u32
+}
+
+#[derive(Serialize)]
+pub struct LevelSnapshot {
+    pub index: usize,
+    pub parent: Option<usize>,
+    pub theme: LevelTheme,
+    pub money: i32,
+    pub round: u32,
+    pub resistor_cost: u32,
+    pub capacitor_cost: u32,
+    pub led_cost: u32,
+    pub cable: Vec<(i32, i32)>,
+    pub towers: Vec<TowerSnapshot>,
+    pub bugs_queued: usize
+}
+
+#[derive(Serialize)]
+pub struct BugSnapshot {
+    pub bug_type: BugType,
+    pub tile: (i32, i32),
+    pub cable_progress: usize,
+    pub health: i32
+}
+
+// what external tools get to see, plain data so it serializes the same everywhere
+#[derive(Serialize)]
+pub struct GameSnapshot {
+    pub tick: u64,
+    pub health: i32,
+    pub round_running: bool,
+    pub active: usize,
+    pub levels: Vec<LevelSnapshot>,
+    pub bugs: Vec<BugSnapshot>
+}
+
+impl GameSnapshot {
+    pub fn capture<'a>(tick: u64, manager: &LevelManager, state: &GameState, balance: &Balance, bugs: impl Iterator<Item = (&'a Transform, &'a BugSprite)>) -> Self {
+        let levels = manager.levels.iter().enumerate().map(|(index, level)| {
+            let towers: Vec<TowerSnapshot> = level.towers.iter().map(|(position, tower)| TowerSnapshot {
+                tower_type: tower.tower_type,
+                position: *position,
+                level_index: tower.level_index,
+                upgrade_factor: tower.upgrade_factor,
+                balance: tower.balance,
+                durability: tower.durability,
+                kills: tower.kills
+            }).collect();
+            LevelSnapshot {
+                index,
+                parent: level.parent,
+                theme: level.theme,
+                money: level.money,
+                round: level.round,
+                resistor_cost: level.resistor_cost(balance),
+                capacitor_cost: level.capacitor_cost(balance),
+                led_cost: level.led_cost(balance),
+                cable: level.cable.clone(),
+                towers,
+                bugs_queued: level.bug_queue.len()
+            }
+        }).collect();
+        let tilemap = &manager.get_current_level().tilemap;
+        let mut bugs: Vec<BugSnapshot> = bugs.map(|(transform, sprite)| BugSnapshot {
+            bug_type: sprite.bug_type.clone(),
+            tile: tilemap.translation_to_grid(transform.translation.truncate()),
+            cable_progress: sprite.cable_progress,
+            health: sprite.health
+        }).collect();
+        bugs.sort_by_key(|bug| std::cmp::Reverse(bug.cable_progress));
+        Self {
+            tick,
+            health: state.health,
+            round_running: state.round_running,
+            active: manager.active,
+            levels,
+            bugs
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CommandSet;
+
+pub struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<PlayerCommand>()
+            .add_event::<TowerBuildEvent>()
+            .add_event::<TowerDeleteEvent>()
+            .add_event::<TowerDonateEvent>()
+            .add_event::<LevelSwitchEvent>()
+            .add_event::<WaveStateChange>()
+            .add_systems(Update, dispatch_player_commands.before(handle_build_tower).in_set(CommandSet).in_set(GameSet::Commands));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,248 @@
+use bevy::ecs::system::CommandQueue;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use crate::bug::{debug_spawn_bug, BugFactory, BugSprite, BugType};
+use crate::input::ActionSet;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::random::GameRng;
+use crate::selection::LevelSwitchEvent;
+use crate::tower::tower_type_name;
+use crate::ui::debug_add_money;
+use crate::wave::GameState;
+
+const CONSOLE_LINES: usize = 12;
+const HELP: &str = "spawn bug|ant|hamster [n], money [+|-]n, hp n, goto n, round n, kill all, tree, seed";
+
+#[derive(Resource, Default)]
+pub struct DevConsole {
+    pub open: bool,
+    pub input: String,
+    pub submitted: Vec<String>,
+    pub log: Vec<String>
+}
+
+impl DevConsole {
+    fn print(&mut self, line: String) {
+        info!("console: {}", line);
+        self.log.push(line);
+        let overflow = self.log.len().saturating_sub(CONSOLE_LINES);
+        self.log.drain(..overflow);
+    }
+}
+
+#[derive(Component)]
+pub struct ConsoleOverlay;
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+pub fn console_closed(console: Res<DevConsole>) -> bool {
+    !console.open
+}
+
+pub fn spawn_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.0),
+                left: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        ConsoleOverlay
+    )).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section("", TextStyle {
+                font: asset_server.load("fonts/QuinqueFive.ttf"),
+                font_size: 10.0,
+                color: Color::GREEN,
+            }),
+            ConsoleText
+        ));
+    });
+}
+
+// runs right after the input is collected, so while the console is open the game never sees the keys typed into it
+pub fn console_input(
+    mut console: ResMut<DevConsole>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut character_reader: EventReader<ReceivedCharacter>
+) {
+    if keys.just_pressed(KeyCode::Backquote) {
+        console.open = !console.open;
+        keys.reset_all();
+        character_reader.clear();
+        return;
+    }
+    if !console.open {
+        character_reader.clear();
+        return;
+    }
+    for event in character_reader.read() {
+        console.input.extend(event.char.chars().filter(|character| !character.is_control()));
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        console.input.pop();
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.print(format!("> {}", line));
+            console.submitted.push(line);
+        }
+    }
+    keys.reset_all();
+}
+
+// H cycles through the levels, as a goto so it is refused during a wave like a typed one
+pub fn debug_level_switch(mut console: ResMut<DevConsole>, manager: Res<LevelManager>, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_pressed(KeyCode::KeyH) {
+        let index = (manager.active + 1) % manager.levels.len();
+        console.submitted.push(format!("goto {}", index));
+    }
+}
+
+pub fn run_console(world: &mut World) {
+    let submitted = std::mem::take(&mut world.resource_mut::<DevConsole>().submitted);
+    for line in submitted {
+        let output = execute(world, &line).unwrap_or_else(|error| format!("error: {}", error));
+        let mut console = world.resource_mut::<DevConsole>();
+        for output_line in output.lines() {
+            console.print(output_line.to_string());
+        }
+    }
+}
+
+fn execute(world: &mut World, line: &str) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["help"] => Ok(HELP.to_string()),
+        ["spawn", bug_type, rest @ ..] => {
+            let bug_type = match *bug_type {
+                "bug" => BugType::Bug,
+                "ant" => BugType::Ant,
+                "hamster" => BugType::Hamster,
+                _ => return Err(format!("unknown bug {}", bug_type))
+            };
+            let count: usize = match rest {
+                [] => 1,
+                [count] => count.parse().map_err(|_| format!("invalid count {}", count))?,
+                _ => return Err("usage: spawn bug|ant|hamster [n]".to_string())
+            };
+            let mut queue = CommandQueue::default();
+            let mut commands = Commands::new(&mut queue, world);
+            let level = world.resource::<LevelManager>().get_current_level();
+            let translation = Vec3::from((level.tilemap.grid_to_translation(level.cable[0]), 2.0));
+            for _ in 0..count {
+                world.resource::<BugFactory>().spawn(&mut commands, &bug_type, Transform::from_translation(translation));
+            }
+            queue.apply(world);
+            Ok(format!("spawned {}", count))
+        },
+        ["money", amount] => {
+            let mut manager = world.resource_mut::<LevelManager>();
+            let level = manager.get_current_level_mut();
+            let value: i32 = amount.trim_start_matches('+').parse().map_err(|_| format!("invalid amount {}", amount))?;
+            if amount.starts_with('+') || amount.starts_with('-') {
+                level.money += value;
+            } else {
+                level.money = value;
+            }
+            Ok(format!("Bit$: {}", level.money))
+        },
+        ["hp", health] => {
+            let health: i32 = health.parse().map_err(|_| format!("invalid hp {}", health))?;
+            world.resource_mut::<GameState>().health = health;
+            Ok(format!("HP: {}", health))
+        },
+        ["goto", index] => {
+            let index: usize = index.parse().map_err(|_| format!("invalid level {}", index))?;
+            if world.resource::<GameState>().round_running {
+                return Err("can't switch levels during a wave".to_string());
+            }
+            let manager = world.resource::<LevelManager>();
+            if index >= manager.levels.len() {
+                return Err(format!("there are only {} levels", manager.levels.len()));
+            }
+            let label = manager.level_label(index);
+            world.send_event(LevelSwitchEvent {index});
+            Ok(format!("going to {}", label))
+        },
+        ["round", round] => {
+            let round: u32 = round.parse().map_err(|_| format!("invalid round {}", round))?;
+            if world.resource::<GameState>().round_running {
+                return Err("can't change the round during a wave".to_string());
+            }
+            world.resource_mut::<LevelManager>().get_current_level_mut().round = round;
+            Ok(format!("next wave is round {}", round + 1))
+        },
+        ["kill", "all"] => {
+            let bugs: Vec<Entity> = world.query_filtered::<Entity, With<BugSprite>>().iter(world).collect();
+            for bug in bugs.iter() {
+                world.despawn(*bug);
+            }
+            world.resource_mut::<LevelManager>().get_current_level_mut().bug_queue.clear();
+            Ok(format!("killed {}", bugs.len()))
+        },
+        ["tree"] => {
+            let manager = world.resource::<LevelManager>();
+            let mut lines = vec![];
+            for index in 0..manager.levels.len() {
+                let level = &manager.levels[index];
+                let marker = if index == manager.active { "*" } else { " " };
+                let towers: Vec<String> = level.towers.iter().map(|(position, tower)| {
+                    format!("{}({},{})->{}", tower_type_name(&tower.tower_type), position.0, position.1, tower.level_index)
+                }).collect();
+                lines.push(format!("{}{}[{}] {} Bit$ {} rnd {} {}",
+                    marker, "  ".repeat(manager.depth(index)), index, level.theme.name(), level.money, level.round, towers.join(" ")));
+            }
+            Ok(lines.join("\n"))
+        },
+        ["seed"] => {
+            let rng = world.resource::<GameRng>();
+            Ok(format!("seed {} state {}", rng.seed, rng.state))
+        },
+        _ => Err(format!("unknown command, try: {}", HELP))
+    }
+}
+
+pub fn update_console(console: Res<DevConsole>, mut overlay_query: Query<&mut Visibility, With<ConsoleOverlay>>, mut text_query: Query<&mut Text, With<ConsoleText>>) {
+    if !console.is_changed() { return; }
+    for mut visibility in overlay_query.iter_mut() {
+        *visibility = if console.open { Visibility::Visible } else { Visibility::Hidden };
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("{}\n> {}_", console.log.join("\n"), console.input);
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConsoleSet;
+
+// only built with the dev-console feature, ` opens it
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<DevConsole>()
+            .add_event::<LevelSwitchEvent>()
+            .add_systems(Startup, spawn_console)
+            .add_systems(PreUpdate, console_input.after(InputSystem).before(ActionSet))
+            .add_systems(Update, (
+                run_console,
+                // the old fixed hotkeys, J spawns an ant, O/P add or take 10 Bit$, H cycles levels
+                (debug_spawn_bug, debug_add_money, debug_level_switch.before(run_console)).run_if(console_closed),
+            ).in_set(ConsoleSet).in_set(GameSet::Commands))
+            .add_systems(Update, update_console.in_set(ConsoleSet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Serialize;
+use crate::bug::{BugKilledEvent, BugLeakedEvent, BugSprite, BugType};
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet, Tick};
+use crate::save::GameLoadedEvent;
+use crate::tower::{TowerFiredEvent, TowerType};
+use crate::wave::GameState;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LoggedEvent {
+    WaveStarted { round: u32 },
+    WaveEnded { round: u32, health: i32 },
+    BugSpawned { bug_type: BugType, position: (i32, i32) },
+    BugKilled { bug_type: BugType, position: (i32, i32), tower: Option<(i32, i32)> },
+    BugLeaked { bug_type: BugType, damage: i32 },
+    TowerBuilt { tower_type: TowerType, position: (i32, i32) },
+    TowerSold { tower_type: TowerType, position: (i32, i32) },
+    TowerFired { tower_type: TowerType, position: (i32, i32), targets: usize },
+    MoneyChanged { money: i32, change: i32 },
+    LevelSwitched { from: usize, to: usize },
+    GameLoaded
+}
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    tick: u64,
+    level: usize,
+    #[serde(flatten)]
+    event: &'a LoggedEvent
+}
+
+// built, sold and paid for are read off the level tree by comparing it with the previous tick,
+// so they are logged no matter which system or command caused them
+#[derive(Resource)]
+pub struct EventLog {
+    writer: BufWriter<File>,
+    towers: Vec<Vec<((i32, i32), TowerType)>>,
+    money: Vec<i32>,
+    active: usize,
+    round_running: bool
+}
+
+impl EventLog {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|error| error.to_string())?;
+        Ok(Self { writer: BufWriter::new(file), towers: vec![], money: vec![], active: 0, round_running: false })
+    }
+
+    fn write(&mut self, tick: u64, level: usize, event: LoggedEvent) {
+        let Ok(line) = serde_json::to_string(&LogLine {tick, level, event: &event}) else { return };
+        if let Err(error) = writeln!(self.writer, "{}", line) {
+            warn!("could not write event: {}", error);
+        }
+    }
+}
+
+fn towers_of(manager: &LevelManager, index: usize) -> Vec<((i32, i32), TowerType)> {
+    manager.levels[index].towers.iter().map(|(position, tower)| (*position, tower.tower_type)).collect()
+}
+
+#[derive(SystemParam)]
+pub struct LoggedReaders<'w, 's> {
+    killed_reader: EventReader<'w, 's, BugKilledEvent>,
+    leaked_reader: EventReader<'w, 's, BugLeakedEvent>,
+    fired_reader: EventReader<'w, 's, TowerFiredEvent>,
+    loaded_reader: EventReader<'w, 's, GameLoadedEvent>
+}
+
+pub fn write_event_log(
+    mut log: ResMut<EventLog>,
+    tick: Res<Tick>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    spawned_query: Query<(&Transform, &BugSprite), Added<BugSprite>>,
+    mut readers: LoggedReaders
+) {
+    let tick = tick.0;
+    let active = manager.active;
+
+    // a load replaces the whole tree, it is taken as the new starting point instead of being diffed
+    if readers.loaded_reader.read().count() > 0 {
+        log.write(tick, active, LoggedEvent::GameLoaded);
+        log.towers = (0..manager.levels.len()).map(|index| towers_of(&manager, index)).collect();
+        log.money = manager.levels.iter().map(|level| level.money).collect();
+        log.active = active;
+        log.round_running = state.round_running;
+    }
+
+    if log.active != active {
+        let from = log.active;
+        log.write(tick, active, LoggedEvent::LevelSwitched {from, to: active});
+        log.active = active;
+    }
+    if log.round_running != state.round_running {
+        let round = manager.get_current_level().round;
+        let event = if state.round_running { LoggedEvent::WaveStarted {round} } else { LoggedEvent::WaveEnded {round, health: state.health} };
+        log.write(tick, active, event);
+        log.round_running = state.round_running;
+    }
+
+    let level = manager.get_current_level();
+    for (transform, bug) in spawned_query.iter() {
+        let position = level.tilemap.translation_to_grid(transform.translation.truncate());
+        log.write(tick, active, LoggedEvent::BugSpawned {bug_type: bug.bug_type.clone(), position});
+    }
+    for event in readers.killed_reader.read() {
+        log.write(tick, active, LoggedEvent::BugKilled {bug_type: event.bug_type.clone(), position: event.position, tower: event.tower});
+    }
+    for event in readers.leaked_reader.read() {
+        log.write(tick, active, LoggedEvent::BugLeaked {bug_type: event.bug_type.clone(), damage: event.damage});
+    }
+    for event in readers.fired_reader.read() {
+        log.write(tick, active, LoggedEvent::TowerFired {tower_type: event.tower_type, position: event.position, targets: event.targets});
+    }
+
+    // levels seen for the first time start out empty, so the starting money and towers get logged too
+    log.towers.resize(manager.levels.len(), vec![]);
+    log.money.resize(manager.levels.len(), 0);
+    for index in 0..manager.levels.len() {
+        let towers = towers_of(&manager, index);
+        let money = manager.levels[index].money;
+        let previous = std::mem::replace(&mut log.towers[index], towers.clone());
+        for (position, tower_type) in towers.iter().filter(|tower| !previous.contains(tower)) {
+            log.write(tick, index, LoggedEvent::TowerBuilt {tower_type: *tower_type, position: *position});
+        }
+        for (position, tower_type) in previous.iter().filter(|tower| !towers.contains(tower)) {
+            log.write(tick, index, LoggedEvent::TowerSold {tower_type: *tower_type, position: *position});
+        }
+        let change = money - log.money[index];
+        if change != 0 {
+            log.write(tick, index, LoggedEvent::MoneyChanged {money, change});
+            log.money[index] = money;
+        }
+    }
+    // flushed every tick so the log is complete up to the last one even if the game is killed
+    let _ = log.writer.flush();
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EventLogSet;
+
+// writes to an EventLog, which has to be created and inserted before the plugin
+pub struct EventLogPlugin;
+
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<BugKilledEvent>()
+            .add_event::<BugLeakedEvent>()
+            .add_event::<TowerFiredEvent>()
+            .add_event::<GameLoadedEvent>()
+            .add_systems(Update, write_event_log.run_if(resource_exists::<LevelManager>).in_set(EventLogSet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,89 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::selection::{GridCursor, TileSelection};
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE};
+
+// in tiles per second at full tilt
+const CURSOR_SPEED: f32 = 8.0;
+const STICK_DEADZONE: f32 = 0.25;
+const CURSOR_COLOR: Color = Color::rgb(1.0, 0.85, 0.0);
+
+const DPAD_STEPS: [(GamepadButtonType, (i32, i32)); 4] = [
+    (GamepadButtonType::DPadUp, (0, 1)),
+    (GamepadButtonType::DPadDown, (0, -1)),
+    (GamepadButtonType::DPadLeft, (-1, 0)),
+    (GamepadButtonType::DPadRight, (1, 0))
+];
+
+// the d-pad hops a whole tile, the left stick glides and the tile under it is the one that counts.
+// runs once per frame before Update, so fast forwarding doesn't speed the cursor up
+pub fn move_grid_cursor(
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    time: Res<Time>,
+    selection: Res<TileSelection>,
+    mut cursor: ResMut<GridCursor>,
+    mut cursor_moved_reader: EventReader<CursorMoved>
+) {
+    // moving the mouse hands the pointer back to it
+    if cursor_moved_reader.read().count() > 0 {
+        cursor.position = None;
+    }
+
+    let mut step = (0, 0);
+    let mut stick = Vec2::ZERO;
+    for gamepad in gamepads.iter() {
+        for (button_type, (x, y)) in DPAD_STEPS {
+            if gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button_type)) {
+                step = (step.0 + x, step.1 + y);
+            }
+        }
+        let x = axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.0);
+        let y = axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.0);
+        stick += Vec2::new(x, y);
+    }
+    if stick.length() < STICK_DEADZONE {
+        stick = Vec2::ZERO;
+    }
+    if step == (0, 0) && stick == Vec2::ZERO { return; }
+
+    // takes over from the mouse on the selected tile, or the middle of the map
+    let position = cursor.position.unwrap_or_else(|| {
+        let (x, y) = selection.tile.unwrap_or((0, 0));
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5)
+    });
+    let position = if step != (0, 0) {
+        let (x, y) = (position.x.floor() as i32 + step.0, position.y.floor() as i32 + step.1);
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5)
+    } else {
+        position + stick.clamp_length_max(1.0) * CURSOR_SPEED * time.delta_seconds()
+    };
+    let half = Vec2::new((MAP_WIDTH / 2) as f32, (MAP_HEIGHT / 2) as f32);
+    // just short of the far edges, so flooring never leaves the map
+    cursor.position = Some(position.clamp(-half, half - Vec2::splat(0.001)));
+}
+
+pub fn draw_grid_cursor(mut gizmos: Gizmos, cursor: Res<GridCursor>, manager: Res<LevelManager>) {
+    let Some(tile) = cursor.tile() else { return };
+    let center = manager.get_current_level().grid_to_translation(tile);
+    gizmos.rect_2d(center, 0.0, Vec2::splat(TILE_SIZE as f32), CURSOR_COLOR);
+    gizmos.rect_2d(center, 0.0, Vec2::splat(TILE_SIZE as f32 - 4.0), CURSOR_COLOR);
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GamepadSet;
+
+// a grid cursor for controllers, the buttons themselves are actions in the input map
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<GridCursor>()
+            .add_systems(PreUpdate, move_grid_cursor.after(InputSystem).in_set(GamepadSet))
+            .add_systems(Update, draw_grid_cursor.in_set(GamepadSet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
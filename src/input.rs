@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::camera::transition_running;
+use crate::command::PlayerCommand;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet, GameSpeed};
+use crate::replay::replaying;
+use crate::selection::{GridCursor, SelectionEvent, TileSelection};
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH};
+use crate::tower::{tile_to_tower_types, TowerType};
+use crate::wave::GameState;
+
+pub const INPUT_MAP_PATH: &str = "input.ron";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum Action {
+    Select,
+    Deselect,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    BuildResistor,
+    BuildCapacitor,
+    BuildLed,
+    ShopPrevious,
+    ShopNext,
+    Build,
+    Sell,
+    Donate,
+    StepInto,
+    StepOut,
+    StartWave,
+    Pause,
+    SpeedUp
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // the same button on any connected gamepad
+    Gamepad(GamepadButtonType)
+}
+
+// keys a settings file can name, by their KeyCode name
+const BINDABLE_KEYS: [KeyCode; 84] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace, KeyCode::Delete, KeyCode::Insert,
+    KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash
+];
+
+const BINDABLE_GAMEPAD_BUTTONS: [GamepadButtonType; 15] = [
+    GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::North, GamepadButtonType::West,
+    GamepadButtonType::C, GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger, GamepadButtonType::LeftTrigger2, GamepadButtonType::RightTrigger, GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select, GamepadButtonType::Start, GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb, GamepadButtonType::RightThumb
+];
+
+impl Binding {
+    // keys go by their KeyCode name (KeyA, Digit1, ArrowUp...), mouse buttons are MouseLeft, MouseRight and MouseMiddle,
+    // gamepad buttons are Gamepad followed by their GamepadButtonType name (GamepadSouth, GamepadLeftTrigger...)
+    pub fn parse(name: &str) -> Option<Binding> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => match name.strip_prefix("Gamepad") {
+                Some(button) => BINDABLE_GAMEPAD_BUTTONS.iter().find(|candidate| format!("{:?}", candidate) == button).map(|button| Binding::Gamepad(*button)),
+                None => BINDABLE_KEYS.iter().find(|key| format!("{:?}", key) == name).map(|key| Binding::Key(*key))
+            }
+        }
+    }
+}
+
+// the d-pad and the left stick are left out, they drive the grid cursor
+const DEFAULT_BINDINGS: [(Action, &[Binding]); 19] = [
+    (Action::Select, &[Binding::Mouse(MouseButton::Left), Binding::Gamepad(GamepadButtonType::South)]),
+    (Action::Deselect, &[Binding::Key(KeyCode::Escape), Binding::Mouse(MouseButton::Right), Binding::Gamepad(GamepadButtonType::East)]),
+    (Action::CursorUp, &[Binding::Key(KeyCode::ArrowUp)]),
+    (Action::CursorDown, &[Binding::Key(KeyCode::ArrowDown)]),
+    (Action::CursorLeft, &[Binding::Key(KeyCode::ArrowLeft)]),
+    (Action::CursorRight, &[Binding::Key(KeyCode::ArrowRight)]),
+    (Action::BuildResistor, &[Binding::Key(KeyCode::Digit1)]),
+    (Action::BuildCapacitor, &[Binding::Key(KeyCode::Digit2)]),
+    (Action::BuildLed, &[Binding::Key(KeyCode::Digit3)]),
+    (Action::ShopPrevious, &[Binding::Key(KeyCode::BracketLeft), Binding::Gamepad(GamepadButtonType::LeftTrigger)]),
+    (Action::ShopNext, &[Binding::Key(KeyCode::BracketRight), Binding::Gamepad(GamepadButtonType::RightTrigger)]),
+    (Action::Build, &[Binding::Key(KeyCode::Enter), Binding::Gamepad(GamepadButtonType::West)]),
+    (Action::Sell, &[Binding::Key(KeyCode::Delete), Binding::Key(KeyCode::KeyX), Binding::Gamepad(GamepadButtonType::North)]),
+    (Action::Donate, &[Binding::Key(KeyCode::KeyD), Binding::Gamepad(GamepadButtonType::Select)]),
+    (Action::StepInto, &[Binding::Key(KeyCode::KeyE), Binding::Gamepad(GamepadButtonType::RightTrigger2)]),
+    (Action::StepOut, &[Binding::Key(KeyCode::KeyQ), Binding::Gamepad(GamepadButtonType::LeftTrigger2)]),
+    (Action::StartWave, &[Binding::Key(KeyCode::KeyN), Binding::Gamepad(GamepadButtonType::Start)]),
+    (Action::Pause, &[Binding::Key(KeyCode::Space), Binding::Gamepad(GamepadButtonType::LeftThumb)]),
+    (Action::SpeedUp, &[Binding::Key(KeyCode::KeyF), Binding::Gamepad(GamepadButtonType::RightThumb)])
+];
+
+#[derive(Resource, Clone)]
+pub struct InputMap {
+    pub bindings: HashMap<Action, Vec<Binding>>
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self { bindings: DEFAULT_BINDINGS.iter().map(|(action, bindings)| (*action, bindings.to_vec())).collect() }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct InputSettings {
+    bindings: HashMap<Action, Vec<String>>
+}
+
+impl InputMap {
+    // actions the file leaves out keep their default bindings, an empty list unbinds one
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let settings: InputSettings = ron::from_str(&text).map_err(|error| error.to_string())?;
+        let mut map = InputMap::default();
+        for (action, names) in settings.bindings {
+            let bindings = names.iter()
+                .map(|name| Binding::parse(name).ok_or_else(|| format!("unknown key {} for {:?}", name, action)))
+                .collect::<Result<Vec<Binding>, String>>()?;
+            map.bindings.insert(action, bindings);
+        }
+        Ok(map)
+    }
+
+    // a missing file just means the defaults, a broken one is reported and ignored
+    pub fn load(path: &str) -> Self {
+        if !Path::new(path).exists() {
+            return InputMap::default();
+        }
+        InputMap::read(path).unwrap_or_else(|error| {
+            warn!("could not read {}: {}", path, error);
+            InputMap::default()
+        })
+    }
+}
+
+// which actions are held and which started this frame, whatever they are bound to
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn clear_just_pressed(&mut self) {
+        self.just_pressed.clear();
+    }
+}
+
+pub fn update_actions(
+    map: Res<InputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut actions: ResMut<ActionState>
+) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    for (action, bindings) in map.bindings.iter() {
+        for binding in bindings {
+            let (pressed, just_pressed) = match binding {
+                Binding::Key(key) => (keys.pressed(*key), keys.just_pressed(*key)),
+                Binding::Mouse(button) => (buttons.pressed(*button), buttons.just_pressed(*button)),
+                Binding::Gamepad(button_type) => (
+                    gamepads.iter().any(|gamepad| gamepad_buttons.pressed(GamepadButton::new(gamepad, *button_type))),
+                    gamepads.iter().any(|gamepad| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, *button_type)))
+                )
+            };
+            if pressed { actions.pressed.insert(*action); }
+            if just_pressed { actions.just_pressed.insert(*action); }
+        }
+    }
+}
+
+const CURSOR_ACTIONS: [(Action, (i32, i32)); 4] = [
+    (Action::CursorUp, (0, 1)),
+    (Action::CursorDown, (0, -1)),
+    (Action::CursorLeft, (-1, 0)),
+    (Action::CursorRight, (1, 0))
+];
+
+const BUILD_ACTIONS: [(Action, TowerType); 3] = [
+    (Action::BuildResistor, TowerType::Resistor),
+    (Action::BuildCapacitor, TowerType::Capacitor),
+    (Action::BuildLed, TowerType::Diode)
+];
+
+fn deselect(tile_selection: &mut TileSelection, selection_event_writer: &mut EventWriter<SelectionEvent>) {
+    let Some(previous) = tile_selection.tile.take() else { return };
+    selection_event_writer.send(SelectionEvent { deselected: Some(previous), selected: None });
+}
+
+// the keyboard cursor is the selection itself, so the shop, the tower panel and the range preview follow it
+pub fn handle_actions(
+    actions: Res<ActionState>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    mut tile_selection: ResMut<TileSelection>,
+    grid_cursor: Option<ResMut<GridCursor>>,
+    mut selection_event_writer: EventWriter<SelectionEvent>,
+    mut command_writer: EventWriter<PlayerCommand>
+) {
+    let level = manager.get_current_level();
+    let previous = tile_selection.tile;
+
+    let moves: Vec<(i32, i32)> = CURSOR_ACTIONS.iter().filter(|(action, _)| actions.just_pressed(*action)).map(|(_, step)| *step).collect();
+    if !moves.is_empty() {
+        // a fresh cursor starts in the middle of the map
+        let tile = match previous {
+            Some(tile) => moves.iter().fold(tile, |tile, step| (tile.0 + step.0, tile.1 + step.1)),
+            None => (0, 0)
+        };
+        let tile = (tile.0.clamp(-MAP_WIDTH / 2, MAP_WIDTH / 2 - 1), tile.1.clamp(-MAP_HEIGHT / 2, MAP_HEIGHT / 2 - 1));
+        if previous != Some(tile) {
+            tile_selection.tile = Some(tile);
+            selection_event_writer.send(SelectionEvent { deselected: previous, selected: tile_selection.tile });
+        }
+        // a controller's grid cursor comes along, or its next Select would jump back to where it was
+        if let Some(mut grid_cursor) = grid_cursor.filter(|grid_cursor| grid_cursor.position.is_some()) {
+            grid_cursor.position = Some(Vec2::new(tile.0 as f32 + 0.5, tile.1 as f32 + 0.5));
+        }
+    }
+
+    if actions.just_pressed(Action::Deselect) {
+        deselect(&mut tile_selection, &mut selection_event_writer);
+    }
+    // whatever is selected stays behind on the level being left
+    if actions.just_pressed(Action::StepInto) && !state.round_running {
+        if let Some(position) = tile_selection.tile.filter(|tile| level.towers.contains_key(tile)) {
+            deselect(&mut tile_selection, &mut selection_event_writer);
+            command_writer.send(PlayerCommand::StepInto { position });
+        }
+    }
+    if actions.just_pressed(Action::StepOut) && !state.round_running && level.parent.is_some() {
+        deselect(&mut tile_selection, &mut selection_event_writer);
+        command_writer.send(PlayerCommand::StepOut);
+    }
+    if actions.just_pressed(Action::StartWave) {
+        command_writer.send(PlayerCommand::StartWave);
+    }
+
+    let Some(position) = tile_selection.tile else { return };
+    let options = tile_to_tower_types(&level.tilemap, position);
+    for (action, tower) in BUILD_ACTIONS {
+        if actions.just_pressed(action) && options.contains(&tower) {
+            command_writer.send(PlayerCommand::Build { tower, position });
+        }
+    }
+    // flips through what the shop offers for the selected tile, Build then buys the highlighted one
+    let step = actions.just_pressed(Action::ShopNext) as i32 - actions.just_pressed(Action::ShopPrevious) as i32;
+    if step != 0 && !options.is_empty() {
+        let next = match tile_selection.shop.and_then(|shop| options.iter().position(|option| *option == shop)) {
+            Some(index) => (index as i32 + step).rem_euclid(options.len() as i32) as usize,
+            None if step > 0 => 0,
+            None => options.len() - 1
+        };
+        tile_selection.shop = Some(options[next]);
+    }
+    if actions.just_pressed(Action::Build) {
+        if let Some(tower) = tile_selection.shop.filter(|shop| options.contains(shop)) {
+            command_writer.send(PlayerCommand::Build { tower, position });
+        }
+    }
+    if actions.just_pressed(Action::Sell) {
+        command_writer.send(PlayerCommand::Sell { position });
+    }
+    if actions.just_pressed(Action::Donate) {
+        command_writer.send(PlayerCommand::Donate { position });
+    }
+}
+
+// pause and speed work during replays too, they only change how fast the ticks go by
+pub fn handle_speed_actions(actions: Res<ActionState>, mut speed: ResMut<GameSpeed>) {
+    if actions.just_pressed(Action::Pause) {
+        speed.paused = !speed.paused;
+    }
+    if actions.just_pressed(Action::SpeedUp) {
+        speed.speed_up();
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionSet;
+
+// keyboard shortcuts, rebindable from input.ron next to the game
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<InputMap>() {
+            app.insert_resource(InputMap::load(INPUT_MAP_PATH));
+        }
+        configure_pipeline(app);
+        app.init_resource::<ActionState>()
+            .add_event::<SelectionEvent>()
+            .add_event::<PlayerCommand>()
+            .add_systems(PreUpdate, update_actions.after(InputSystem).in_set(ActionSet))
+            .add_systems(Update, (
+                handle_actions.run_if(not(transition_running)).run_if(not(replaying)),
+                handle_speed_actions,
+            ).in_set(ActionSet).in_set(GameSet::Input));
+    }
+}
\ No newline at end of file
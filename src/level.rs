@@ -1,14 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::format;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::bug::BugType;
+use crate::random::GameRng;
 use crate::cable::{random_path, set_cable};
-use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE, Tilemap, TileType, TilemapFactory};
-use crate::camera::CAMERA_OFFSET;
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE, TileLayer, Tilemap, TileSet, TilemapFactory};
+use crate::camera::{CameraKeyframe, CameraTransition, TransitionSettings, CAMERA_OFFSET};
 use crate::selection::{LevelSwitchEvent, SelectionEvent, TileSelection};
-use crate::tower::{TowerType, TowerSprite, RESISTOR_COST, LED_COST, CAPACITOR_COST};
-use crate::ui::STEP_OUT_COORDS;
+use crate::tower::{handle_donate, tower_type_name, Balance, TowerType, TowerSprite};
+use crate::pipeline::{configure_pipeline, GameSet};
 
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum LevelTheme {
     Black ,
     Blue,
@@ -16,23 +20,85 @@ pub enum LevelTheme {
     Red
 }
 
-fn get_background(theme: &LevelTheme, asset_server: &Res<AssetServer>) -> Handle<Image> {
-    let filename = match theme {
-        LevelTheme::Black => "black",
-        LevelTheme::Blue => "blue",
-        LevelTheme::Green => "green",
-        LevelTheme::Red => "red"
-    };
-    asset_server.load(format!("backgrounds/{}.png", filename))
+impl LevelTheme {
+    pub const ALL: [LevelTheme; 4] = [LevelTheme::Black, LevelTheme::Blue, LevelTheme::Green, LevelTheme::Red];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LevelTheme::Black => "Black",
+            LevelTheme::Blue => "Blue",
+            LevelTheme::Green => "Green",
+            LevelTheme::Red => "Red"
+        }
+    }
+
+    pub fn for_depth(depth: usize) -> LevelTheme {
+        match depth % 4 {
+            0 => LevelTheme::Green,
+            1 => LevelTheme::Blue,
+            2 => LevelTheme::Red,
+            _ => LevelTheme::Black
+        }
+    }
+
+    pub fn background(&self) -> &'static str {
+        match self {
+            LevelTheme::Black => "backgrounds/black.png",
+            LevelTheme::Blue => "backgrounds/blue.png",
+            LevelTheme::Green => "backgrounds/green.png",
+            LevelTheme::Red => "backgrounds/red.png"
+        }
+    }
+
+    pub fn tileset(&self) -> &'static str {
+        match self {
+            LevelTheme::Black => "tiles/TileSet2.png",
+            LevelTheme::Blue => "tiles/TileSet.png",
+            LevelTheme::Green => "tiles/TileSet3.png",
+            LevelTheme::Red => "tiles/TileSet2.png"
+        }
+    }
+
+    // number of rows in the tileset's 16px grid
+    pub fn tileset_rows(&self) -> usize {
+        match self {
+            LevelTheme::Black | LevelTheme::Red => 4,
+            LevelTheme::Blue | LevelTheme::Green => 8
+        }
+    }
+
+    pub fn text_color(&self) -> Color {
+        match self {
+            LevelTheme::Black => Color::rgb(0.8, 0.8, 0.8),
+            LevelTheme::Blue => Color::rgb(0., 0.8, 1.),
+            LevelTheme::Green => Color::rgb(0., 1., 0.),
+            LevelTheme::Red => Color::rgb(1., 0.4, 0.3)
+        }
+    }
+
+    // there is only one track, deeper levels play it at a different pace
+    pub fn music(&self) -> (&'static str, f32) {
+        match self {
+            LevelTheme::Black => ("sounds/music.ogg", 0.8),
+            LevelTheme::Blue => ("sounds/music.ogg", 1.1),
+            LevelTheme::Green => ("sounds/music.ogg", 1.0),
+            LevelTheme::Red => ("sounds/music.ogg", 1.2)
+        }
+    }
+}
+
+fn get_background(theme: &LevelTheme, asset_server: &AssetServer) -> Handle<Image> {
+    asset_server.load(theme.background())
 }
 
 pub struct Level {
     offset: Vec2,
     pub(crate) tilemap: Tilemap,
     pub(crate) cable: Vec<(i32, i32)>,
-    theme: LevelTheme,
+    pub(crate) theme: LevelTheme,
     pub money: i32,
-    pub towers: HashMap<(i32, i32), TowerSprite>,
+    // ordered by position, so towers fire and get listed the same way on every run
+    pub towers: BTreeMap<(i32, i32), TowerSprite>,
     pub parent: Option<usize>,
     pub round: u32,
     pub resistor_count: u32,
@@ -43,32 +109,54 @@ pub struct Level {
     pub bug_frames: u32
 }
 
+#[derive(Component)]
+pub struct LevelBackground;
+
 impl Level {
-    pub fn setup(&mut self, commands: &mut Commands, asset_server: &Res<AssetServer>) {
-        commands.spawn( (
-            SpriteBundle {
-                texture: get_background(&self.theme, asset_server),
-                transform: Transform::from_xyz(self.offset.x, self.offset.y, 0.0),
-                sprite: Sprite {
-                    custom_size: Some(Vec2::new((MAP_WIDTH * TILE_SIZE) as f32, (MAP_HEIGHT * TILE_SIZE) as f32)),
+    pub fn setup(&mut self, commands: &mut Commands, asset_server: Option<&AssetServer>) {
+        if let Some(asset_server) = asset_server {
+            commands.spawn( (
+                SpriteBundle {
+                    texture: get_background(&self.theme, asset_server),
+                    transform: Transform::from_xyz(self.offset.x, self.offset.y, 0.0),
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new((MAP_WIDTH * TILE_SIZE) as f32, (MAP_HEIGHT * TILE_SIZE) as f32)),
+                        ..default()
+                    },
                     ..default()
                 },
-                ..default()
-            },
-        ));
+                LevelBackground
+            ));
+        }
         set_cable(&mut self.tilemap, commands, &self.cable)
     }
 
-    pub fn resistor_cost(&self) -> u32 {
-        RESISTOR_COST * (2_u32).pow(self.resistor_count)
+    pub fn resistor_cost(&self, balance: &Balance) -> u32 {
+        balance.resistor_cost * (2_u32).pow(self.resistor_count)
+    }
+
+    pub fn capacitor_cost(&self, balance: &Balance) -> u32 {
+        balance.capacitor_cost * (2_u32).pow(self.capacitor_count)
+    }
+
+    pub fn led_cost(&self, balance: &Balance) -> u32 {
+        balance.led_cost * (2_u32).pow(self.led_count)
+    }
+
+    pub fn cable(&self) -> &[(i32, i32)] {
+        &self.cable
     }
 
-    pub fn capacitor_cost(&self) -> u32 {
-        CAPACITOR_COST * (2_u32).pow(self.capacitor_count)
+    pub fn grid_to_translation(&self, position: (i32, i32)) -> Vec2 {
+        self.tilemap.grid_to_translation(position)
     }
 
-    pub fn led_cost(&self) -> u32 {
-        LED_COST * (2_u32).pow(self.led_count)
+    pub fn cost(&self, tower_type: &TowerType, balance: &Balance) -> u32 {
+        match tower_type {
+            TowerType::Resistor => self.resistor_cost(balance),
+            TowerType::Capacitor => self.capacitor_cost(balance),
+            TowerType::Diode => self.led_cost(balance)
+        }
     }
 }
 #[derive(Resource)]
@@ -80,19 +168,23 @@ pub struct LevelManager {
 impl LevelManager {
     pub fn switch_view(&mut self, index: usize, transform: &mut Transform) {
         self.active = index;
+        transform.translation = self.camera_translation(index);
+    }
+
+    pub fn camera_translation(&self, index: usize) -> Vec3 {
         let level = &self.levels[index];
-        transform.translation = Vec3::new(level.offset.x + CAMERA_OFFSET, level.offset.y, 0.0);
+        Vec3::new(level.offset.x + CAMERA_OFFSET, level.offset.y, 0.0)
     }
 
-    pub fn add_level(&mut self, theme: LevelTheme, cable: Vec<(i32, i32)>, tilemap_factory: &TilemapFactory, commands: &mut Commands, asset_server: &Res<AssetServer>, parent: Option<usize>) -> usize {
+    pub fn add_level(&mut self, theme: LevelTheme, cable: Vec<(i32, i32)>, tilemap_factory: &TilemapFactory, commands: &mut Commands, asset_server: Option<&AssetServer>, parent: Option<usize>) -> usize {
         let offset = Vec2::new((self.levels.len() * 2000) as f32, 0.0);
         let mut level = Level {
             offset,
-            tilemap: tilemap_factory.instantiate(offset),
+            tilemap: tilemap_factory.instantiate(offset, theme.tileset()),
             cable,
             theme,
             money: 0,
-            towers: HashMap::new(),
+            towers: BTreeMap::new(),
             parent,
             round: 0,
             resistor_count: 0,
@@ -113,16 +205,86 @@ impl LevelManager {
     pub fn get_current_level_mut(&mut self) -> &mut Level {
         &mut self.levels[self.active]
     }
+    pub fn owning_tower_mut(&mut self, index: usize) -> Option<&mut TowerSprite> {
+        let parent = self.levels[index].parent?;
+        self.levels[parent].towers.values_mut().find(|tower| tower.level_index == index)
+    }
+    pub fn transition_keyframes(&self, previous: usize, next: usize, zoom: f32) -> ((CameraKeyframe, CameraKeyframe), (CameraKeyframe, CameraKeyframe)) {
+        let leaving = CameraKeyframe::level(self.camera_translation(previous), 1.0);
+        let entering = CameraKeyframe::level(self.camera_translation(next), 1.0);
+        if self.levels[next].parent == Some(previous) {
+            // step into: dive into the tower, then the child level fades in around it
+            if let Some(position) = self.owning_tower_position(next) {
+                let tile = self.levels[previous].tilemap.grid_to_translation(position);
+                return ((leaving, CameraKeyframe::tile(tile, zoom)), (entering, entering));
+            }
+        } else if self.levels[previous].parent == Some(next) {
+            // step out: the reverse, pulling back out of the tower in the parent level
+            if let Some(position) = self.owning_tower_position(previous) {
+                let tile = self.levels[next].tilemap.grid_to_translation(position);
+                return ((leaving, leaving), (CameraKeyframe::tile(tile, zoom), entering));
+            }
+        }
+        ((leaving, leaving), (entering, entering))
+    }
+    pub fn owning_tower_position(&self, index: usize) -> Option<(i32, i32)> {
+        let parent = self.levels[index].parent?;
+        self.levels[parent].towers.iter().find(|(_, tower)| tower.level_index == index).map(|(position, _)| *position)
+    }
+    pub fn owning_tower(&self, index: usize) -> Option<&TowerSprite> {
+        let parent = self.levels[index].parent?;
+        self.levels[parent].towers.values().find(|tower| tower.level_index == index)
+    }
+    pub fn depth(&self, index: usize) -> usize {
+        match self.levels[index].parent {
+            Some(parent) => self.depth(parent) + 1,
+            None => 0
+        }
+    }
+    pub fn children(&self, index: usize) -> Vec<usize> {
+        (0..self.levels.len()).filter(|child| self.levels[*child].parent == Some(index)).collect()
+    }
+    pub fn level_label(&self, index: usize) -> String {
+        let Some(parent) = self.levels[index].parent else { return "Root".to_string() };
+        match self.levels[parent].towers.iter().find(|(_, tower)| tower.level_index == index) {
+            Some((position, tower)) => format!("{}({},{})", tower_type_name(&tower.tower_type), position.0, position.1),
+            None => format!("Sold({})", index)
+        }
+    }
+    pub fn breadcrumb(&self, index: usize) -> String {
+        match self.levels[index].parent {
+            Some(parent) => format!("{} > {}", self.breadcrumb(parent), self.level_label(index)),
+            None => self.level_label(index)
+        }
+    }
 }
 
 #[derive(Resource)]
 pub struct TilemapFactoryResource(pub TilemapFactory);
 
-pub fn setup_main_level(mut commands: Commands, mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>, assets: Res<AssetServer>) {
-    let tilemap_factory = TilemapFactory {
-        atlas_layout: texture_atlases.add(TextureAtlasLayout::from_grid(Vec2::splat(16.0), 8, 8, None, None)),
-        texture: assets.load("tiles/TileSet3.png")
-    };
+// what the root level begins with, round counts the waves already behind it
+#[derive(Resource, Clone, Copy, Serialize, Deserialize)]
+pub struct StartingLevel {
+    pub money: i32,
+    pub round: u32
+}
+
+impl Default for StartingLevel {
+    fn default() -> Self {
+        Self { money: 300, round: 0 }
+    }
+}
+
+pub fn setup_main_level(mut commands: Commands, mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>, assets: Res<AssetServer>, rng: Res<GameRng>, start: Res<StartingLevel>) {
+    let mut tilesets = HashMap::new();
+    // themes share tilesets, each one is only loaded once
+    for theme in LevelTheme::ALL {
+        tilesets.entry(theme.tileset()).or_insert_with(|| TileSet {
+            atlas_layout: texture_atlases.add(TextureAtlasLayout::from_grid(Vec2::splat(16.0), 8, theme.tileset_rows(), None, None)),
+            texture: assets.load(theme.tileset())
+        });
+    }
+    let tilemap_factory = TilemapFactory {tilesets};
 
     let path = vec![
         (-6, 2), (-5, 2), (-4, 2), (-3, 2), (-2, 2), (-1, 2), (0, 2), (1, 2), (2, 2), (3, 2), (4, 2), (5, 2), (6, 2), (6, 3), (5, 3), (4, 3), (3, 3), (2, 3), (1, 3), (0, 3)
@@ -131,45 +293,64 @@ pub fn setup_main_level(mut commands: Commands, mut texture_atlases: ResMut<Asse
         (5, 5), (4, 5), (4, 6), (3, 6), (3, 7), (2, 7), (2, 6), (1, 6), (0, 6), (0, 5), (-1, 5), (-2, 5), (-2, 4), (-3, 4), (-3, 3), (-4, 3), (-4, 2), (-5, 2), (-5, 1), (-6, 1)
     ];
     let mut manager = LevelManager {levels: vec![], active: 0};
-    manager.add_level(LevelTheme::Green, random_path(2137), &tilemap_factory, &mut commands, &assets, None);
+    manager.add_level(LevelTheme::Green, random_path(rng.seed as usize), &tilemap_factory, &mut commands, Some(&assets), None);
     // manager.add_level(LevelTheme::Black, path2.clone(), &tilemap_factory, &mut commands, &assets, 0);
-    manager.get_current_level_mut().money = 300;
+    manager.get_current_level_mut().money = start.money;
+    manager.get_current_level_mut().round = start.round;
 
     commands.insert_resource(manager);
     commands.insert_resource(TilemapFactoryResource(tilemap_factory));
 }
 
-pub fn debug_level_switch(mut switch_writer: EventWriter<LevelSwitchEvent>, mut manager: ResMut<LevelManager>, keys: Res<ButtonInput<KeyCode>>, mut camera_query: Query<&mut Transform, With<Camera>>) {
-    let mut camera_position = camera_query.single_mut();
-
-    if keys.just_pressed(KeyCode::KeyH) {
-        let new_index = (manager.active + 1) % manager.levels.len();
-        println!("[DEBUG] switching to {}/{}", new_index + 1, manager.levels.len());
-        // manager.switch_view(new_index, &mut camera_position)
-        switch_writer.send(LevelSwitchEvent{index: new_index, deselect: IVec3::new(0,0,2137)});
-    }
+// all missing in headless apps, which switch levels without any animation
+#[derive(SystemParam)]
+pub struct SwitchTransition<'w> {
+    transition: Option<ResMut<'w, CameraTransition>>,
+    settings: Option<Res<'w, TransitionSettings>>,
+    asset_server: Option<Res<'w, AssetServer>>
 }
 
 pub fn handle_level_switch(
     mut camera_query: Query<&mut Transform, With<Camera>>,
     mut commands: Commands,
     mut level_switch_reader: EventReader<LevelSwitchEvent>,
-    mut manager: ResMut<LevelManager>, selection_tile: Res<TileSelection>,
-    mut selection_writer: EventWriter<SelectionEvent>
+    mut manager: ResMut<LevelManager>,
+    mut selection_writer: EventWriter<SelectionEvent>,
+    mut switch_transition: SwitchTransition
 ) {
     for event in level_switch_reader.read() {
-        let mut level = manager.get_current_level_mut();
-        level.tilemap.set(&mut commands, event.deselect, None);
-        let mut camera_transform = camera_query.single_mut();
-        manager.switch_view(event.index, &mut camera_transform);
-        selection_writer.send(SelectionEvent{deselected: Some(IVec3::new(0,0,2137)), selected: None});
-
-        let mut switched_level = manager.get_current_level_mut();
-        if switched_level.parent.is_some() {
-            switched_level.tilemap.set(&mut commands, IVec3::new(STEP_OUT_COORDS[0].0, STEP_OUT_COORDS[0].1, 10), Some(TileType::StepOut1));
-            switched_level.tilemap.set(&mut commands, IVec3::new(STEP_OUT_COORDS[1].0, STEP_OUT_COORDS[1].1, 10), Some(TileType::StepOut2));
-            switched_level.tilemap.set(&mut commands, IVec3::new(STEP_OUT_COORDS[2].0, STEP_OUT_COORDS[2].1, 10), Some(TileType::StepOut3));
-            switched_level.tilemap.set(&mut commands, IVec3::new(STEP_OUT_COORDS[3].0, STEP_OUT_COORDS[3].1, 10), Some(TileType::StepOut4));
+        // whatever was selected stays behind on the level being left
+        manager.get_current_level_mut().tilemap.clear_layer(&mut commands, TileLayer::Selection);
+        let previous = manager.active;
+        match camera_query.get_single_mut() {
+            Ok(mut camera_transform) => manager.switch_view(event.index, &mut camera_transform),
+            Err(_) => manager.active = event.index
+        }
+
+        if let (Some(transition), Some(settings), Some(asset_server)) = (switch_transition.transition.as_mut(), &switch_transition.settings, &switch_transition.asset_server) {
+            let (leave, enter) = manager.transition_keyframes(previous, event.index, settings.zoom);
+            let background = get_background(&manager.levels[event.index].theme, asset_server);
+            transition.start(settings, leave, enter, background);
+        }
+        selection_writer.send(SelectionEvent{deselected: None, selected: None});
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelSet;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<GameRng>() {
+            app.insert_resource(GameRng::new(2137));
         }
+        configure_pipeline(app);
+        app.init_resource::<StartingLevel>()
+            .add_event::<LevelSwitchEvent>()
+            .add_event::<SelectionEvent>()
+            .add_systems(Startup, setup_main_level.run_if(resource_exists::<AssetServer>))
+            .add_systems(Update, handle_level_switch.after(handle_donate).in_set(LevelSet).in_set(GameSet::Commands));
     }
 }
\ No newline at end of file
@@ -0,0 +1,77 @@
+pub mod level;
+pub mod tilemap;
+pub mod camera;
+pub mod cable;
+pub mod bug;
+pub mod animations;
+pub mod ui;
+pub mod tower;
+pub mod selection;
+pub mod sounds;
+pub mod wave;
+pub mod navigator;
+pub mod spatial;
+pub mod random;
+pub mod simulation;
+pub mod pipeline;
+pub mod save;
+pub mod replay;
+pub mod command;
+pub mod protocol;
+pub mod autoplay;
+pub mod cli;
+pub mod overlay;
+pub mod eventlog;
+pub mod scenario;
+pub mod preview;
+pub mod input;
+pub mod gamepad;
+#[cfg(feature = "dev-console")]
+pub mod console;
+
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+use crate::autoplay::AutoplayPlugin;
+use crate::bug::BugPlugin;
+use crate::camera::CameraPlugin;
+use crate::command::CommandPlugin;
+use crate::level::LevelPlugin;
+use crate::overlay::OverlayPlugin;
+use crate::preview::PreviewPlugin;
+use crate::input::InputMapPlugin;
+use crate::gamepad::GamepadPlugin;
+use crate::replay::ReplayPlugin;
+use crate::save::SavePlugin;
+use crate::selection::SelectionPlugin;
+use crate::sounds::AudioPlugin;
+use crate::tower::TowerPlugin;
+use crate::ui::UiPlugin;
+use crate::wave::WavePlugin;
+
+// everything the windowed game needs, the gameplay plugins alone also run under MinimalPlugins
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(LevelPlugin)
+            .add(BugPlugin)
+            .add(TowerPlugin)
+            .add(WavePlugin)
+            .add(CommandPlugin)
+            .add(CameraPlugin)
+            .add(SelectionPlugin)
+            .add(UiPlugin)
+            .add(AudioPlugin)
+            .add(SavePlugin)
+            .add(ReplayPlugin)
+            .add(AutoplayPlugin)
+            .add(OverlayPlugin)
+            .add(PreviewPlugin)
+            .add(InputMapPlugin)
+            .add(GamepadPlugin);
+        #[cfg(feature = "dev-console")]
+        let group = group.add(console::ConsolePlugin);
+        group
+    }
+}
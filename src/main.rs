@@ -1,76 +1,134 @@
-mod level;
-mod tilemap;
-mod camera;
-mod cable;
-mod bug;
-mod animations;
-mod ui;
-mod tower;
-mod selection;
-mod sounds;
-mod wave;
-
 use bevy::prelude::*;
-use bevy::window::{EnabledButtons, PresentMode};
-use crate::bug::{debug_spawn_bug, load_bugs, move_bugs, check_bug_health};
-use crate::camera::setup_camera;
-use crate::level::{setup_main_level, debug_level_switch, handle_level_switch};
-use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH, TILE_SIZE};
-use crate::animations::{BugsAnimationTimer, led_tower_animation, bugs_animation, SelectionAnimationTimer, config_selection_animation, selection_animation};
-use crate::ui::{spawn_text, MENU_WIDTH, update_stats_text, debug_add_money, tower_options, tower_control_panel};
-use crate::selection::{tile_selection, TileSelection, SelectionEvent, TowerBuildEvent, LevelSwitchEvent};
-use crate::tower::{handle_build_tower, handle_resistor, handle_led, handle_capacitor, handle_capacitor_bullet};
-use crate::sounds::{setup_sounds};
-use crate::wave::{setup_game, WaveStateChange, handle_continue_button, spawn_wave, end_wave, handle_loss};
+use bevy::window::{PresentMode, WindowMode, WindowResizeConstraints};
+use step_into::GamePlugins;
+use step_into::autoplay::{Autoplay, AutoplayStrategy};
+use step_into::camera::{VIEW_HEIGHT, VIEW_WIDTH};
+use step_into::cli::{Options, USAGE};
+use step_into::eventlog::EventLog;
+use step_into::level::StartingLevel;
+use step_into::protocol::{ProtocolConnection, ProtocolPlugin};
+use step_into::random::GameRng;
+use step_into::replay::{ReplayFile, ReplayPlayer};
+use step_into::save::{SaveFile, StartingSave};
+use step_into::simulation::{add_event_log, run_autoplay, run_replay, run_simulation, simulation_app, SimulationConfig};
+use step_into::wave::GameState;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        std::process::exit(1);
+    });
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    let replay = options.replay.as_ref().map(|path| {
+        ReplayFile::read(path).unwrap_or_else(|error| {
+            eprintln!("could not read replay {}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+    // read up front so a broken file is reported before anything opens
+    let save = options.level.as_ref().map(|path| {
+        SaveFile::read(path).unwrap_or_else(|error| {
+            eprintln!("could not read level {}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+    let connection = options.protocol.as_ref().map(|transport| {
+        ProtocolConnection::open(transport).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        })
+    });
+    let event_log = options.event_log.as_ref().map(|path| {
+        EventLog::create(path).unwrap_or_else(|error| {
+            eprintln!("could not create event log {}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+    let defaults = SimulationConfig::default();
+    let config = SimulationConfig {
+        seed: options.seed.unwrap_or(defaults.seed),
+        money: options.money.unwrap_or(defaults.money),
+        health: options.health.unwrap_or(defaults.health),
+        round: options.start_round.map_or(defaults.round, |round| round - 1),
+        save,
+        ..defaults
+    };
+
+    if options.headless {
+        if let Some(connection) = connection {
+            let mut app = simulation_app(&config);
+            app.insert_resource(connection)
+                .add_plugins(ProtocolPlugin);
+            add_event_log(&mut app, event_log);
+            app.run();
+            return;
+        }
+        if options.autoplay {
+            let strategy = AutoplayStrategy::default();
+            let rounds = strategy.rounds;
+            let report = run_autoplay(&config, strategy, event_log);
+            println!("{}", report);
+            // a failing exit code lets CI notice when a balance change makes the game unbeatable
+            if report.rounds_survived < rounds {
+                std::process::exit(1);
+            }
+            return;
+        }
+        match replay {
+            Some(replay) => println!("{}", run_replay(replay, event_log)),
+            None => println!("{}", run_simulation(&config, event_log))
+        }
+        return;
+    }
+    let mut app = App::new();
+    match replay {
+        Some(replay) => {
+            app.insert_resource(GameRng::new(replay.seed))
+                .insert_resource(replay.start)
+                .insert_resource(GameState {health: replay.health, round_running: false});
+            if let Some(save) = replay.save.clone() {
+                app.insert_resource(StartingSave(save));
+            }
+            app.insert_resource(ReplayPlayer::new(replay));
+        },
+        None => {
+            app.insert_resource(GameRng::new(config.seed))
+                .insert_resource(StartingLevel {money: config.money, round: config.round})
+                .insert_resource(GameState {health: config.health, round_running: false});
+            if let Some(save) = config.save {
+                app.insert_resource(StartingSave(save));
+            }
+        }
+    }
+    if options.autoplay {
+        app.insert_resource(Autoplay::new(true, AutoplayStrategy {think_ticks: 30, ..default()}));
+    }
+    app.add_plugins(DefaultPlugins
             .set(ImagePlugin::default_nearest())
             .set(WindowPlugin {
                 primary_window: Some(Window {
                     title: "STEP INTO".into(),
-                    resolution: ((MAP_WIDTH * TILE_SIZE) as f32 + MENU_WIDTH, (MAP_HEIGHT * TILE_SIZE) as f32).into(),
-                    resizable: false,
-                    present_mode: PresentMode::AutoVsync,
-                    // mode: bevy::window::WindowMode::SizedFullscreen,
-                    enabled_buttons: EnabledButtons {
-                        maximize: false,
+                    resolution: (VIEW_WIDTH, VIEW_HEIGHT).into(),
+                    resize_constraints: WindowResizeConstraints {
+                        min_width: VIEW_WIDTH / 2.0,
+                        min_height: VIEW_HEIGHT / 2.0,
                         ..default()
                     },
+                    present_mode: PresentMode::AutoVsync,
+                    mode: if options.fullscreen { WindowMode::SizedFullscreen } else { WindowMode::Windowed },
                     ..default()
                 }),
                 ..default()
             }),
         )
-        .insert_resource(TileSelection{tile: None})
-        .insert_resource(BugsAnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
-        .insert_resource(SelectionAnimationTimer(Timer::from_seconds(0.5, TimerMode::Repeating)))
-        .add_event::<SelectionEvent>()
-        .add_event::<TowerBuildEvent>()
-        .add_event::<LevelSwitchEvent>()
-        .add_event::<WaveStateChange>()
-        .add_systems(Startup, (setup_camera, setup_main_level, load_bugs, spawn_text, setup_sounds, setup_game))
-        .add_systems(Update, (
-            move_bugs,
-            bugs_animation,
-            config_selection_animation,
-            // selection_animation,
-            update_stats_text,
-            tile_selection,
-            tower_options,
-            handle_build_tower,
-            handle_resistor,
-            handle_led,
-            check_bug_health,
-            led_tower_animation,
-            handle_capacitor,
-            handle_capacitor_bullet,
-            tower_control_panel,
-            handle_level_switch.before(tower_options).before(tower_control_panel),
-            handle_continue_button.after(tile_selection),
-            spawn_wave.after(handle_continue_button),
-        ))
-        .add_systems(Update, (end_wave.after(spawn_wave), handle_loss))
-        .run();
+        .add_plugins(GamePlugins);
+    if let Some(connection) = connection {
+        app.insert_resource(connection)
+            .add_plugins(ProtocolPlugin);
+    }
+    add_event_log(&mut app, event_log);
+    app.run();
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::level::LevelManager;
+use crate::command::PlayerCommand;
+use crate::selection::{SelectionEvent, TileSelection};
+use crate::wave::GameState;
+use crate::save::GameLoadedEvent;
+use crate::ui::ThemedText;
+
+#[derive(Component)]
+pub struct Breadcrumb;
+
+#[derive(Component)]
+pub struct NavigatorPanel;
+
+#[derive(Component)]
+pub struct NavigatorNode {
+    pub index: usize
+}
+
+const NAVIGATOR_COLOR: Color = Color::rgb(0., 1., 0.);
+const NAVIGATOR_INDENT: f32 = 16.0;
+
+pub fn spawn_navigator(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/QuinqueFive.ttf"),
+                font_size: 10.0,
+                color: NAVIGATOR_COLOR,
+            },
+        )
+            .with_text_justify(JustifyText::Left)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(6.0),
+                left: Val::Px(6.0),
+                ..default()
+            }),
+        Breadcrumb,
+        ThemedText,
+    ));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(24.0),
+                left: Val::Px(6.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(6.0)),
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.8).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        NavigatorPanel,
+    ));
+}
+
+pub fn toggle_navigator(keys: Res<ButtonInput<KeyCode>>, mut panel_query: Query<&mut Visibility, With<NavigatorPanel>>) {
+    if !keys.just_pressed(KeyCode::Tab) { return; }
+    let mut visibility = panel_query.single_mut();
+    *visibility = match *visibility {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => Visibility::Hidden
+    };
+}
+
+fn tree_order(manager: &LevelManager, index: usize, order: &mut Vec<usize>) {
+    order.push(index);
+    for child in manager.children(index) {
+        tree_order(manager, child, order);
+    }
+}
+
+#[derive(SystemParam)]
+pub struct NavigatorQueries<'w, 's> {
+    panel_query: Query<'w, 's, Entity, With<NavigatorPanel>>,
+    node_query: Query<'w, 's, (&'static NavigatorNode, &'static Children, &'static mut BackgroundColor)>,
+    text_query: Query<'w, 's, &'static mut Text, Without<Breadcrumb>>,
+    breadcrumb_query: Query<'w, 's, &'static mut Text, With<Breadcrumb>>
+}
+
+pub fn update_navigator(
+    mut commands: Commands,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    asset_server: Res<AssetServer>,
+    mut queries: NavigatorQueries,
+    mut loaded_reader: EventReader<GameLoadedEvent>,
+    mut spawned: Local<usize>
+) {
+    queries.breadcrumb_query.single_mut().sections[0].value = manager.breadcrumb(manager.active);
+
+    // levels are never removed, so the tree only has to be rebuilt when one gets added or a save is loaded
+    if *spawned != manager.levels.len() || loaded_reader.read().count() > 0 {
+        *spawned = manager.levels.len();
+        let panel = queries.panel_query.single();
+        commands.entity(panel).despawn_descendants();
+        let mut order = vec![];
+        for root in (0..manager.levels.len()).filter(|index| manager.levels[*index].parent.is_none()) {
+            tree_order(&manager, root, &mut order);
+        }
+        commands.entity(panel).with_children(|parent| {
+            for index in order {
+                parent.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            margin: UiRect::left(Val::Px(NAVIGATOR_INDENT * manager.depth(index) as f32)),
+                            padding: UiRect::all(Val::Px(2.0)),
+                            ..default()
+                        },
+                        background_color: Color::NONE.into(),
+                        ..default()
+                    },
+                    NavigatorNode { index },
+                )).with_children(|button| {
+                    button.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: asset_server.load("fonts/QuinqueFive.ttf"),
+                                font_size: 10.0,
+                                color: NAVIGATOR_COLOR,
+                            },
+                        ),
+                        ThemedText,
+                    ));
+                });
+            }
+        });
+        return;
+    }
+
+    for (node, children, mut background) in queries.node_query.iter_mut() {
+        let level = &manager.levels[node.index];
+        let running = node.index == manager.active && state.round_running;
+        let Some(mut text) = children.first().and_then(|child| queries.text_query.get_mut(*child).ok()) else { continue };
+        text.sections[0].value = format!(
+            "{} {} Rnd:{} Bit$:{}{}",
+            manager.level_label(node.index),
+            level.theme.name(),
+            level.round,
+            level.money,
+            if running { " [WAVE]" } else { "" }
+        );
+        *background = if node.index == manager.active {
+            Color::rgba(0., 1., 0., 0.25).into()
+        } else {
+            Color::NONE.into()
+        };
+    }
+}
+
+pub fn navigator_click(
+    node_query: Query<(&Interaction, &NavigatorNode), Changed<Interaction>>,
+    panel_query: Query<&Visibility, With<NavigatorPanel>>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    mut tile_selection: ResMut<TileSelection>,
+    mut selection_event_writer: EventWriter<SelectionEvent>,
+    mut command_writer: EventWriter<PlayerCommand>
+) {
+    if *panel_query.single() == Visibility::Hidden { return; }
+    for (interaction, node) in node_query.iter() {
+        if *interaction != Interaction::Pressed { continue; }
+        if state.round_running || node.index == manager.active { continue; }
+        let previous = tile_selection.tile;
+        tile_selection.tile = None;
+        selection_event_writer.send(SelectionEvent { deselected: previous, selected: None });
+        command_writer.send(PlayerCommand::SwitchLevel { index: node.index });
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use crate::bug::BugSprite;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::save::GameLoadedEvent;
+use crate::tilemap::TILE_SIZE;
+use crate::tower::{tower_range, CapacitorBullet, TowerType};
+
+const CABLE_COLOR: Color = Color::YELLOW;
+const LED_COLOR: Color = Color::CYAN;
+const CAPACITOR_COLOR: Color = Color::FUCHSIA;
+const DISABLED_COLOR: Color = Color::RED;
+const TARGET_COLOR: Color = Color::ORANGE;
+const BULLET_COLOR: Color = Color::WHITE;
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    // levels whose cable already got index labels, levels are only ever appended
+    labelled: usize
+}
+
+#[derive(Component)]
+pub struct CableLabel;
+
+pub fn toggle_overlay(keys: Res<ButtonInput<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+pub fn overlay_enabled(overlay: Res<DebugOverlay>) -> bool {
+    overlay.enabled
+}
+
+pub fn draw_overlay(
+    mut gizmos: Gizmos,
+    manager: Res<LevelManager>,
+    bug_query: Query<(&Transform, &BugSprite)>,
+    bullet_query: Query<(&Transform, &CapacitorBullet)>
+) {
+    for level in manager.levels.iter() {
+        gizmos.linestrip_2d(level.cable.iter().map(|tile| level.tilemap.grid_to_translation(*tile)), CABLE_COLOR);
+        for (position, tower) in level.towers.iter() {
+            let Some(range) = tower_range(&tower.tower_type) else { continue };
+            let color = if tower.is_disabled() { DISABLED_COLOR } else if tower.tower_type == TowerType::Diode { LED_COLOR } else { CAPACITOR_COLOR };
+            gizmos.circle_2d(level.tilemap.grid_to_translation(*position), range * TILE_SIZE as f32, color);
+        }
+    }
+
+    // bugs only walk the active level
+    let level = manager.get_current_level();
+    for (transform, bug) in bug_query.iter() {
+        let Some(tile) = level.cable.get(bug.cable_progress) else { continue };
+        let target = level.tilemap.grid_to_translation(*tile);
+        gizmos.line_2d(transform.translation.truncate(), target, TARGET_COLOR);
+        gizmos.rect_2d(target, 0.0, Vec2::splat(TILE_SIZE as f32 * 0.3), TARGET_COLOR);
+    }
+    for (bullet_transform, bullet) in bullet_query.iter() {
+        let Ok((target_transform, _)) = bug_query.get(bullet.target) else { continue };
+        gizmos.line_2d(bullet_transform.translation.truncate(), target_transform.translation.truncate(), BULLET_COLOR);
+    }
+}
+
+// gizmos can't draw text, so the cable indices are regular text sprites kept around while the overlay is on
+pub fn update_cable_labels(
+    mut commands: Commands,
+    mut overlay: ResMut<DebugOverlay>,
+    manager: Res<LevelManager>,
+    asset_server: Res<AssetServer>,
+    mut loaded_reader: EventReader<GameLoadedEvent>,
+    label_query: Query<Entity, With<CableLabel>>
+) {
+    let reloaded = loaded_reader.read().count() > 0;
+    if !overlay.enabled || reloaded {
+        if overlay.labelled == 0 { return; }
+        for entity in label_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        overlay.labelled = 0;
+        if !overlay.enabled { return; }
+    }
+    for level in manager.levels.iter().skip(overlay.labelled) {
+        for (index, tile) in level.cable.iter().enumerate() {
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section(index.to_string(), TextStyle {
+                        font: asset_server.load("fonts/QuinqueFive.ttf"),
+                        font_size: 8.0,
+                        color: CABLE_COLOR,
+                    }),
+                    transform: Transform::from_translation(level.tilemap.grid_to_translation(*tile).extend(10.0)),
+                    ..default()
+                },
+                CableLabel
+            ));
+        }
+    }
+    overlay.labelled = manager.levels.len();
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OverlaySet;
+
+// F3 shows cables, tower ranges, where every bug is heading and what every bullet is chasing
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<DebugOverlay>()
+            .add_event::<GameLoadedEvent>()
+            .add_systems(Update, toggle_overlay.in_set(OverlaySet).in_set(GameSet::Input))
+            .add_systems(Update, (
+                draw_overlay.run_if(overlay_enabled),
+                update_cable_labels,
+            ).in_set(OverlaySet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
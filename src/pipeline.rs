@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use crate::input::ActionState;
+
+// every Update system runs in exactly one of these, in this order each frame:
+//   Input        reads mouse/keyboard/ui and only sends events
+//   Commands     applies those events (tower builds, level switches, wave starts) to the game state
+//   Simulation   advances waves, bugs and towers by one tick
+//   Presentation redraws tiles, text, animations, camera and music from the resulting state
+// an event sent in a later stage is picked up by an earlier one on the next frame, never the same one
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    Input,
+    Commands,
+    Simulation,
+    Presentation
+}
+
+// counts Update runs since startup, every frame advances the simulation by exactly one of them
+// (none while paused, several when fast forwarding)
+#[derive(Resource, Default)]
+pub struct Tick(pub u64);
+
+pub fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+pub const SPEEDS: [u32; 3] = [1, 2, 4];
+
+// pausing freezes the tick along with the simulation, so commands given while paused replay on the same tick
+#[derive(Resource)]
+pub struct GameSpeed {
+    pub paused: bool,
+    pub ticks_per_frame: u32
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        Self { paused: false, ticks_per_frame: 1 }
+    }
+}
+
+impl GameSpeed {
+    pub fn speed_up(&mut self) {
+        let next = SPEEDS.iter().position(|speed| *speed == self.ticks_per_frame).map_or(0, |index| (index + 1) % SPEEDS.len());
+        self.ticks_per_frame = SPEEDS[next];
+    }
+}
+
+pub fn not_paused(speed: Res<GameSpeed>) -> bool {
+    !speed.paused
+}
+
+// true while fast_forward runs the extra ticks of a frame
+#[derive(Resource, Default)]
+pub struct ExtraTick(pub bool);
+
+// for systems driven by the frame's time rather than the tick, they would run ahead when fast forwarding
+pub fn once_per_frame(extra_tick: Res<ExtraTick>) -> bool {
+    !extra_tick.0
+}
+
+// every extra tick is a full Update run, input that was just pressed only counts for the first one
+pub fn fast_forward(world: &mut World) {
+    let speed = world.resource::<GameSpeed>();
+    let extra_ticks = if speed.paused { 0 } else { speed.ticks_per_frame - 1 };
+    world.resource_mut::<ExtraTick>().0 = true;
+    for _ in 0..extra_ticks {
+        if let Some(mut keys) = world.get_resource_mut::<ButtonInput<KeyCode>>() {
+            keys.clear();
+        }
+        if let Some(mut buttons) = world.get_resource_mut::<ButtonInput<MouseButton>>() {
+            buttons.clear();
+        }
+        if let Some(mut gamepad_buttons) = world.get_resource_mut::<ButtonInput<GamepadButton>>() {
+            gamepad_buttons.clear();
+        }
+        if let Some(mut actions) = world.get_resource_mut::<ActionState>() {
+            actions.clear_just_pressed();
+        }
+        world.resource_mut::<Tick>().0 += 1;
+        world.run_schedule(Update);
+    }
+    world.resource_mut::<ExtraTick>().0 = false;
+}
+
+// every plugin calls this, the sets are only configured once per app
+pub fn configure_pipeline(app: &mut App) {
+    if app.world.contains_resource::<Tick>() { return; }
+    app.init_resource::<Tick>()
+        .init_resource::<GameSpeed>()
+        .init_resource::<ExtraTick>()
+        .add_systems(First, advance_tick.run_if(not_paused))
+        .add_systems(Last, fast_forward)
+        .configure_sets(Update, (GameSet::Input, GameSet::Commands, GameSet::Simulation, GameSet::Presentation).chain())
+        .configure_sets(Update, GameSet::Simulation.run_if(not_paused));
+}
\ No newline at end of file
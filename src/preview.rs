@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::selection::TileSelection;
+use crate::tilemap::{TileLayer, TILE_SIZE};
+use crate::tower::{tile_to_tower_types, tower_range, tower_type_to_tile_type, TowerType};
+
+const RANGE_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.6);
+const COVERED_COLOR: Color = Color::rgba(0.2, 1.0, 0.4, 0.8);
+const GHOST_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.5);
+
+// level, tile and tower type of the ghost currently on the preview layer
+#[derive(Resource, Default)]
+pub struct RangePreview {
+    ghost: Option<(usize, (i32, i32), TowerType)>
+}
+
+// hovering a shop icon previews the tower that would be built, otherwise a selected tower previews itself
+fn previewed_tower(manager: &LevelManager, selection: &TileSelection) -> Option<((i32, i32), TowerType, bool)> {
+    let level = manager.get_current_level();
+    let tile = selection.tile?;
+    let shop = selection.shop.filter(|tower_type| tile_to_tower_types(&level.tilemap, tile).contains(tower_type));
+    match shop {
+        Some(tower_type) => Some((tile, tower_type, true)),
+        None => level.towers.get(&tile).map(|tower| (tile, tower.tower_type, false))
+    }
+}
+
+pub fn update_range_preview(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    mut manager: ResMut<LevelManager>,
+    mut preview: ResMut<RangePreview>,
+    selection: Res<TileSelection>
+) {
+    let previewed = previewed_tower(&manager, &selection);
+
+    let ghost = previewed.filter(|(_, _, ghost)| *ghost).map(|(tile, tower_type, _)| (manager.active, tile, tower_type));
+    if ghost != preview.ghost {
+        if let Some((index, _, _)) = preview.ghost {
+            // the level may be gone after loading a save
+            if let Some(level) = manager.levels.get_mut(index) {
+                level.tilemap.clear_layer(&mut commands, TileLayer::Preview);
+            }
+        }
+        if let Some((_, tile, tower_type)) = ghost {
+            let tilemap = &mut manager.get_current_level_mut().tilemap;
+            tilemap.set(&mut commands, TileLayer::Preview, tile, Some(tower_type_to_tile_type(&tower_type)));
+            tilemap.tint(&mut commands, TileLayer::Preview, tile, GHOST_COLOR);
+        }
+        preview.ghost = ghost;
+    }
+
+    let Some((tile, tower_type, _)) = previewed else { return };
+    let level = manager.get_current_level();
+    let center = level.grid_to_translation(tile);
+    let tile_size = Vec2::splat(TILE_SIZE as f32);
+    let Some(range) = tower_range(&tower_type) else {
+        gizmos.rect_2d(center, 0.0, tile_size, COVERED_COLOR);
+        return
+    };
+    gizmos.circle_2d(center, range * TILE_SIZE as f32, RANGE_COLOR);
+    // same test the towers use when looking for bugs, measured from the tile centres
+    for cable_tile in level.cable() {
+        let position = level.grid_to_translation(*cable_tile);
+        if position.distance(center) <= range * TILE_SIZE as f32 {
+            gizmos.rect_2d(position, 0.0, tile_size * 0.8, COVERED_COLOR);
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreviewSet;
+
+// range outline and a ghost of the tower while shopping, and the range of a selected tower
+pub struct PreviewPlugin;
+
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<RangePreview>()
+            .add_systems(Update, update_range_preview.in_set(PreviewSet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::Serialize;
+use crate::bug::BugSprite;
+use crate::command::{GameSnapshot, PlayerCommand};
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet, Tick};
+use crate::tower::Balance;
+use crate::wave::{GameState, WaveStateChange};
+
+// one JSON object per line both ways: PlayerCommands in, snapshots and errors out
+#[derive(Clone, Debug)]
+pub enum Transport {
+    Stdio,
+    Tcp(u16)
+}
+
+impl Transport {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            None if spec == "stdio" => Ok(Transport::Stdio),
+            Some(("tcp", port)) => port.parse().map(Transport::Tcp).map_err(|_| format!("invalid port {}", port)),
+            _ => Err(format!("unknown transport {} (expected stdio or tcp:PORT)", spec))
+        }
+    }
+}
+
+enum Incoming {
+    Line(String),
+    Closed
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Outgoing {
+    Snapshot(GameSnapshot),
+    Error { message: String }
+}
+
+#[derive(Resource)]
+pub struct ProtocolConnection {
+    incoming: Mutex<Receiver<Incoming>>,
+    outgoing: Sender<String>,
+    // at most one command is applied per tick, so a command always sees the state the previous one left
+    pending: VecDeque<PlayerCommand>,
+    respond: bool
+}
+
+impl ProtocolConnection {
+    fn send(&self, message: &Outgoing) {
+        if let Ok(line) = serde_json::to_string(message) {
+            let _ = self.outgoing.send(line);
+        }
+    }
+
+    pub fn open(transport: &Transport) -> Result<Self, String> {
+        let (incoming_sender, incoming) = channel();
+        let (outgoing, outgoing_receiver) = channel::<String>();
+        match transport {
+            Transport::Stdio => {
+                thread::spawn(move || {
+                    for line in std::io::stdin().lock().lines() {
+                        let Ok(line) = line else { break };
+                        if incoming_sender.send(Incoming::Line(line)).is_err() { return; }
+                    }
+                    let _ = incoming_sender.send(Incoming::Closed);
+                });
+                thread::spawn(move || {
+                    for line in outgoing_receiver {
+                        let mut stdout = std::io::stdout().lock();
+                        let _ = writeln!(stdout, "{}", line);
+                        let _ = stdout.flush();
+                    }
+                });
+            }
+            Transport::Tcp(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", *port)).map_err(|error| format!("could not listen on port {}: {}", port, error))?;
+                let client: Arc<Mutex<Option<TcpStream>>> = Arc::new(Mutex::new(None));
+                let writer_client = client.clone();
+                // one client at a time, the next one is accepted once it disconnects
+                thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        let Ok(stream) = stream else { continue };
+                        let Ok(reader) = stream.try_clone() else { continue };
+                        *client.lock().unwrap() = Some(stream);
+                        for line in BufReader::new(reader).lines() {
+                            let Ok(line) = line else { break };
+                            if incoming_sender.send(Incoming::Line(line)).is_err() { return; }
+                        }
+                        *client.lock().unwrap() = None;
+                    }
+                });
+                thread::spawn(move || {
+                    for line in outgoing_receiver {
+                        if let Some(stream) = writer_client.lock().unwrap().as_mut() {
+                            let _ = writeln!(stream, "{}", line);
+                        }
+                    }
+                });
+            }
+        }
+        Ok(ProtocolConnection {incoming: Mutex::new(incoming), outgoing, pending: VecDeque::new(), respond: false})
+    }
+}
+
+pub fn read_protocol(mut connection: ResMut<ProtocolConnection>, mut command_writer: EventWriter<PlayerCommand>, mut app_exit: EventWriter<AppExit>) {
+    let lines: Vec<Incoming> = connection.incoming.lock().unwrap().try_iter().collect();
+    for incoming in lines {
+        match incoming {
+            Incoming::Line(line) if line.trim().is_empty() => {},
+            Incoming::Line(line) => match serde_json::from_str::<PlayerCommand>(&line) {
+                Ok(command) => connection.pending.push_back(command),
+                Err(error) => connection.send(&Outgoing::Error {message: error.to_string()})
+            },
+            // the controlling process is gone, nobody is left to play
+            Incoming::Closed => { app_exit.send(AppExit); }
+        }
+    }
+    if let Some(command) = connection.pending.pop_front() {
+        command_writer.send(command);
+        connection.respond = true;
+    }
+}
+
+// answers every command with the state it resulted in, and reports waves starting and ending on its own
+pub fn write_snapshots(
+    mut connection: ResMut<ProtocolConnection>,
+    mut wave_state_reader: EventReader<WaveStateChange>,
+    tick: Res<Tick>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    balance: Res<Balance>,
+    bug_query: Query<(&Transform, &BugSprite)>
+) {
+    let wave_changed = wave_state_reader.read().count() > 0;
+    if !connection.respond && !wave_changed { return; }
+    connection.respond = false;
+    connection.send(&Outgoing::Snapshot(GameSnapshot::capture(tick.0, &manager, &state, &balance, bug_query.iter())));
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtocolSet;
+
+// serves a ProtocolConnection, which has to be opened and inserted before the plugin
+pub struct ProtocolPlugin;
+
+impl Plugin for ProtocolPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<PlayerCommand>()
+            .add_event::<WaveStateChange>()
+            .add_systems(Update, read_protocol.in_set(ProtocolSet).in_set(GameSet::Input))
+            .add_systems(Update, write_snapshots.in_set(ProtocolSet).in_set(GameSet::Presentation));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,22 @@
+use bevy::prelude::*;
+
+// small splitmix64 generator, so a run can be reproduced from its seed
+#[derive(Resource)]
+pub struct GameRng {
+    pub seed: u64,
+    pub state: u64
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
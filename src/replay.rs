@@ -0,0 +1,235 @@
+use std::fs::{self, File};
+use std::io::Write;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::command::dispatch_player_commands;
+use crate::bug::BugSprite;
+use crate::level::{LevelManager, StartingLevel};
+use crate::pipeline::{configure_pipeline, GameSet, Tick};
+use crate::random::GameRng;
+use crate::save::{handle_load, GameLoadedEvent, SaveFile};
+use crate::selection::{LevelSwitchEvent, TowerBuildEvent, TowerDeleteEvent, TowerDonateEvent};
+use crate::tower::TowerType;
+use crate::wave::{GameState, WaveStateChange};
+
+pub const REPLAY_VERSION: u32 = 4;
+pub const REPLAY_PATH: &str = "replay.ron";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ReplayCommand {
+    Build { tower: TowerType, position: (i32, i32) },
+    Delete { position: (i32, i32) },
+    Donate { position: (i32, i32) },
+    SwitchLevel { index: usize },
+    Continue
+}
+
+// commands are stamped with the tick they were applied on, together with the seed and starting state that is enough to rerun a game
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub version: u32,
+    pub seed: u64,
+    pub start: StartingLevel,
+    pub health: i32,
+    // a game recorded after a load starts from the loaded save instead, on its first tick
+    pub save: Option<SaveFile>,
+    pub commands: Vec<(u64, ReplayCommand)>
+}
+
+// on disk the header and every command get a line of their own, so recording only ever appends
+#[derive(Serialize, Deserialize)]
+struct ReplayHeader {
+    version: u32,
+    seed: u64,
+    start: StartingLevel,
+    health: i32,
+    save: Option<SaveFile>
+}
+
+fn command_line(tick: u64, command: &ReplayCommand) -> Result<String, String> {
+    ron::to_string(&(tick, command)).map(|line| line + "\n").map_err(|error| error.to_string())
+}
+
+impl ReplayFile {
+    fn header_line(&self) -> Result<String, String> {
+        let header = ReplayHeader {version: self.version, seed: self.seed, start: self.start, health: self.health, save: self.save.clone()};
+        ron::to_string(&header).map(|line| line + "\n").map_err(|error| error.to_string())
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let mut text = self.header_line()?;
+        for (tick, command) in self.commands.iter() {
+            text += &command_line(*tick, command)?;
+        }
+        fs::write(path, text).map_err(|error| error.to_string())
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+        let Some((header, command_lines)) = lines.split_first() else { return Err("replay is empty".to_string()) };
+        let header: ReplayHeader = ron::from_str(header).map_err(|error| error.to_string())?;
+        if header.version != REPLAY_VERSION {
+            return Err(format!("replay version {} is not supported (expected {})", header.version, REPLAY_VERSION));
+        }
+        let mut commands = vec![];
+        for (index, line) in command_lines.iter().enumerate() {
+            match ron::from_str(line) {
+                Ok(command) => commands.push(command),
+                // a crash while recording can cut the last command short, everything before it still plays
+                Err(_) if index + 1 == command_lines.len() => break,
+                Err(error) => return Err(format!("line {}: {}", index + 2, error))
+            }
+        }
+        Ok(ReplayFile {version: header.version, seed: header.seed, start: header.start, health: header.health, save: header.save, commands})
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.commands.last().map_or(0, |(tick, _)| *tick)
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: String,
+    pub replay: ReplayFile,
+    // the tick before the replay's first one, a load restarts the recording from the tick it happened on
+    origin: u64,
+    // opened with the first command, which also writes the header
+    file: Option<File>
+}
+
+impl ReplayRecorder {
+    fn append(&mut self, commands: &[(u64, ReplayCommand)]) -> Result<(), String> {
+        let mut text = String::new();
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.path).map_err(|error| error.to_string())?);
+            text += &self.replay.header_line()?;
+        }
+        for (tick, command) in commands {
+            text += &command_line(*tick, command)?;
+        }
+        // unbuffered, each frame's commands are on disk as soon as they are recorded
+        self.file.as_mut().unwrap().write_all(text.as_bytes()).map_err(|error| error.to_string())
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub replay: ReplayFile,
+    pub cursor: usize
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: ReplayFile) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.cursor >= self.replay.commands.len()
+    }
+}
+
+pub fn replaying(player: Option<Res<ReplayPlayer>>) -> bool {
+    player.is_some()
+}
+
+pub fn start_recording(mut commands: Commands, rng: Res<GameRng>, start: Res<StartingLevel>, state: Res<GameState>, player: Option<Res<ReplayPlayer>>) {
+    if player.is_some() { return; }
+    commands.insert_resource(ReplayRecorder {
+        path: REPLAY_PATH.to_string(),
+        replay: ReplayFile {version: REPLAY_VERSION, seed: rng.seed, start: *start, health: state.health, save: None, commands: vec![]},
+        origin: 0,
+        file: None
+    });
+}
+
+// whatever was recorded before a load can't lead up to the loaded game, so the replay starts over from it
+pub fn restart_recording(
+    mut loaded_reader: EventReader<GameLoadedEvent>,
+    tick: Res<Tick>,
+    mut recorder: ResMut<ReplayRecorder>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    rng: Res<GameRng>,
+    bug_query: Query<(&Transform, &BugSprite)>
+) {
+    if loaded_reader.read().last().is_none() { return; }
+    let save = SaveFile::capture(&manager, &state, &rng, bug_query.iter());
+    recorder.replay = ReplayFile {version: REPLAY_VERSION, seed: rng.seed, start: recorder.replay.start, health: state.health, save: Some(save), commands: vec![]};
+    recorder.origin = tick.0 - 1;
+    recorder.file = None;
+    // written right away, the replay from before the load must not stay behind
+    if let Err(error) = recorder.append(&[]) {
+        warn!("could not write {}: {}", recorder.path, error);
+    }
+}
+
+pub fn record_commands(
+    tick: Res<Tick>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut tower_build_reader: EventReader<TowerBuildEvent>,
+    mut tower_delete_reader: EventReader<TowerDeleteEvent>,
+    mut tower_donate_reader: EventReader<TowerDonateEvent>,
+    mut level_switch_reader: EventReader<LevelSwitchEvent>,
+    mut wave_state_reader: EventReader<WaveStateChange>
+) {
+    let mut recorded = vec![];
+    recorded.extend(tower_build_reader.read().map(|event| ReplayCommand::Build {tower: event.tower, position: event.position}));
+    recorded.extend(tower_delete_reader.read().map(|event| ReplayCommand::Delete {position: event.position}));
+    recorded.extend(tower_donate_reader.read().map(|event| ReplayCommand::Donate {position: event.position}));
+    recorded.extend(level_switch_reader.read().map(|event| ReplayCommand::SwitchLevel {index: event.index}));
+    // waves ending are the game's doing, only starting one is a player command
+    recorded.extend(wave_state_reader.read().filter(|event| event.running).map(|_| ReplayCommand::Continue));
+    if recorded.is_empty() { return; }
+
+    let recorded: Vec<_> = recorded.into_iter().map(|command| (tick.0 - recorder.origin, command)).collect();
+    if let Err(error) = recorder.append(&recorded) {
+        warn!("could not write {}: {}", recorder.path, error);
+    }
+    recorder.replay.commands.extend(recorded);
+}
+
+pub fn play_commands(
+    tick: Res<Tick>,
+    mut player: ResMut<ReplayPlayer>,
+    mut tower_build_writer: EventWriter<TowerBuildEvent>,
+    mut tower_delete_writer: EventWriter<TowerDeleteEvent>,
+    mut tower_donate_writer: EventWriter<TowerDonateEvent>,
+    mut level_switch_writer: EventWriter<LevelSwitchEvent>,
+    mut wave_state_writer: EventWriter<WaveStateChange>
+) {
+    while let Some((command_tick, command)) = player.replay.commands.get(player.cursor) {
+        if *command_tick > tick.0 { break; }
+        match command.clone() {
+            ReplayCommand::Build {tower, position} => { tower_build_writer.send(TowerBuildEvent {tower, position}); },
+            ReplayCommand::Delete {position} => { tower_delete_writer.send(TowerDeleteEvent {position}); },
+            ReplayCommand::Donate {position} => { tower_donate_writer.send(TowerDonateEvent {position}); },
+            ReplayCommand::SwitchLevel {index} => { level_switch_writer.send(LevelSwitchEvent {index}); },
+            ReplayCommand::Continue => { wave_state_writer.send(WaveStateChange {running: true}); }
+        }
+        player.cursor += 1;
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplaySet;
+
+// records into replay.ron, or plays a ReplayPlayer back if one was inserted before the plugin
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<TowerBuildEvent>()
+            .add_event::<TowerDeleteEvent>()
+            .add_event::<TowerDonateEvent>()
+            .add_event::<LevelSwitchEvent>()
+            .add_event::<WaveStateChange>()
+            .add_event::<GameLoadedEvent>()
+            .add_systems(Startup, start_recording)
+            .add_systems(Update, play_commands.run_if(resource_exists::<ReplayPlayer>).in_set(ReplaySet).in_set(GameSet::Input))
+            .add_systems(Update, restart_recording.run_if(resource_exists::<ReplayRecorder>).after(handle_load).before(record_commands).in_set(ReplaySet).in_set(GameSet::Commands))
+            .add_systems(Update, record_commands.run_if(resource_exists::<ReplayRecorder>).after(dispatch_player_commands).in_set(ReplaySet).in_set(GameSet::Commands));
+    }
+}
\ No newline at end of file
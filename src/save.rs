@@ -0,0 +1,296 @@
+use std::fs;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::bug::{BugFactory, BugSprite, BugType};
+use crate::camera::CameraTransition;
+use crate::level::{LevelBackground, LevelManager, LevelTheme, TilemapFactoryResource};
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::random::GameRng;
+use crate::selection::{SelectionEvent, TileSelection};
+use crate::tilemap::{TileLayer, TilemapFactory};
+use crate::tower::{tower_type_to_tile_type, CapacitorBullet, TowerSprite};
+use crate::wave::{handle_continue_button, GameState, WaveStateChange};
+
+// bump whenever SaveFile changes shape, old files are refused instead of half-loaded
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_PATH: &str = "save.ron";
+pub const AUTOSAVE_PATH: &str = "autosave.ron";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedLevel {
+    pub cable: Vec<(i32, i32)>,
+    pub theme: LevelTheme,
+    pub money: i32,
+    pub round: u32,
+    pub parent: Option<usize>,
+    pub resistor_count: u32,
+    pub capacitor_count: u32,
+    pub led_count: u32,
+    pub towers: Vec<((i32, i32), TowerSprite)>,
+    pub bug_queue: Vec<BugType>,
+    pub bug_queue_speed: u32,
+    pub bug_frames: u32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedBug {
+    pub translation: [f32; 3],
+    pub sprite: BugSprite
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub health: i32,
+    pub round_running: bool,
+    pub seed: u64,
+    pub rng_state: u64,
+    pub active: usize,
+    pub levels: Vec<SavedLevel>,
+    // bugs only ever walk the active level, so these all belong to it
+    pub bugs: Vec<SavedBug>
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32
+}
+
+impl SaveFile {
+    pub fn capture<'a>(manager: &LevelManager, state: &GameState, rng: &GameRng, bugs: impl Iterator<Item = (&'a Transform, &'a BugSprite)>) -> Self {
+        let levels = manager.levels.iter().map(|level| {
+            let towers: Vec<((i32, i32), TowerSprite)> = level.towers.iter().map(|(position, tower)| (*position, tower.clone())).collect();
+            SavedLevel {
+                cable: level.cable.clone(),
+                theme: level.theme,
+                money: level.money,
+                round: level.round,
+                parent: level.parent,
+                resistor_count: level.resistor_count,
+                capacitor_count: level.capacitor_count,
+                led_count: level.led_count,
+                towers,
+                bug_queue: level.bug_queue.clone(),
+                bug_queue_speed: level.bug_queue_speed,
+                bug_frames: level.bug_frames
+            }
+        }).collect();
+        let bugs = bugs.map(|(transform, sprite)| SavedBug {
+            translation: transform.translation.to_array(),
+            sprite: sprite.clone()
+        }).collect();
+        Self {
+            version: SAVE_VERSION,
+            health: state.health,
+            round_running: state.round_running,
+            seed: rng.seed,
+            rng_state: rng.state,
+            active: manager.active,
+            levels,
+            bugs
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|error| error.to_string())?;
+        fs::write(path, text).map_err(|error| error.to_string())
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let header: SaveHeader = ron::from_str(&text).map_err(|error| error.to_string())?;
+        if header.version != SAVE_VERSION {
+            return Err(format!("save version {} is not supported (expected {})", header.version, SAVE_VERSION));
+        }
+        let save: SaveFile = ron::from_str(&text).map_err(|error| error.to_string())?;
+        if save.levels.is_empty() || save.active >= save.levels.len() {
+            return Err("save has no active level".to_string());
+        }
+        Ok(save)
+    }
+
+    // tiles and backgrounds are spawned again through Level::setup, bugs through the factory
+    pub fn restore(self, commands: &mut Commands, tilemap_factory: &TilemapFactory, bug_factory: &BugFactory, asset_server: Option<&AssetServer>) -> (LevelManager, GameState, GameRng) {
+        let mut manager = LevelManager {levels: vec![], active: self.active};
+        for saved in self.levels {
+            let index = manager.add_level(saved.theme, saved.cable, tilemap_factory, commands, asset_server, saved.parent);
+            let level = &mut manager.levels[index];
+            level.money = saved.money;
+            level.round = saved.round;
+            level.resistor_count = saved.resistor_count;
+            level.capacitor_count = saved.capacitor_count;
+            level.led_count = saved.led_count;
+            level.bug_queue = saved.bug_queue;
+            level.bug_queue_speed = saved.bug_queue_speed;
+            level.bug_frames = saved.bug_frames;
+            for (position, tower) in saved.towers {
+                level.tilemap.set(commands, TileLayer::Tower, position, Some(tower_type_to_tile_type(&tower.tower_type)));
+                level.towers.insert(position, tower);
+            }
+        }
+        for bug in self.bugs {
+            let entity = bug_factory.spawn(commands, &bug.sprite.bug_type, Transform::from_translation(Vec3::from_array(bug.translation)));
+            commands.entity(entity).insert(bug.sprite);
+        }
+        let state = GameState {health: self.health, round_running: self.round_running};
+        let rng = GameRng {seed: self.seed, state: self.rng_state};
+        (manager, state, rng)
+    }
+}
+
+#[derive(Event)]
+pub struct SaveGameEvent {
+    pub path: String
+}
+
+#[derive(Event)]
+pub struct LoadGameEvent {
+    pub path: String
+}
+
+// sent after a load replaced the level tree, anything caching it has to rebuild
+#[derive(Event)]
+pub struct GameLoadedEvent;
+
+// a save the game opens with instead of a fresh level, loaded on the first tick through the same path as F9
+#[derive(Resource)]
+pub struct StartingSave(pub SaveFile);
+
+pub fn save_keys(keys: Res<ButtonInput<KeyCode>>, mut save_writer: EventWriter<SaveGameEvent>, mut load_writer: EventWriter<LoadGameEvent>) {
+    if keys.just_pressed(KeyCode::F5) {
+        save_writer.send(SaveGameEvent {path: SAVE_PATH.to_string()});
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        load_writer.send(LoadGameEvent {path: SAVE_PATH.to_string()});
+    }
+}
+
+pub fn handle_save(
+    mut save_reader: EventReader<SaveGameEvent>,
+    mut wave_state_reader: EventReader<WaveStateChange>,
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    rng: Res<GameRng>,
+    bug_query: Query<(&Transform, &BugSprite)>
+) {
+    let mut paths: Vec<String> = save_reader.read().map(|event| event.path.clone()).collect();
+    // autosave whenever a wave is over, the one sent on startup has nothing worth keeping yet
+    if wave_state_reader.read().any(|event| !event.running) && manager.levels.iter().any(|level| level.round > 0) {
+        paths.push(AUTOSAVE_PATH.to_string());
+    }
+    if paths.is_empty() { return; }
+    let save = SaveFile::capture(&manager, &state, &rng, bug_query.iter());
+    for path in paths {
+        match save.write(&path) {
+            Ok(()) => info!("saved to {}", path),
+            Err(error) => warn!("could not save to {}: {}", path, error)
+        }
+    }
+}
+
+type Spawned = Or<(With<BugSprite>, With<CapacitorBullet>, With<LevelBackground>)>;
+
+// what a save is rebuilt with, and the entities of the old game it replaces
+#[derive(SystemParam)]
+pub struct RestoreContext<'w, 's> {
+    tilemap_factory: Res<'w, TilemapFactoryResource>,
+    bug_factory: Res<'w, BugFactory>,
+    asset_server: Option<Res<'w, AssetServer>>,
+    spawned_query: Query<'w, 's, Entity, Spawned>
+}
+
+// everything showing the old game that has to catch up with the loaded one
+#[derive(SystemParam)]
+pub struct LoadedView<'w, 's> {
+    tile_selection: Option<ResMut<'w, TileSelection>>,
+    transition: Option<ResMut<'w, CameraTransition>>,
+    camera_query: Query<'w, 's, (&'static mut Transform, &'static mut OrthographicProjection), With<Camera>>,
+    selection_writer: EventWriter<'w, SelectionEvent>,
+    loaded_writer: EventWriter<'w, GameLoadedEvent>
+}
+
+// the starting save, or else whatever file was asked for last
+#[derive(SystemParam)]
+pub struct LoadRequests<'w, 's> {
+    starting: Option<Res<'w, StartingSave>>,
+    load_reader: EventReader<'w, 's, LoadGameEvent>
+}
+
+impl LoadRequests<'_, '_> {
+    fn next(&mut self, commands: &mut Commands) -> Option<(String, SaveFile)> {
+        if let Some(starting) = self.starting.as_ref() {
+            commands.remove_resource::<StartingSave>();
+            return Some(("the starting save".to_string(), starting.0.clone()));
+        }
+        // only the last request of a frame matters
+        let event = self.load_reader.read().last()?;
+        match SaveFile::read(&event.path) {
+            Ok(save) => Some((event.path.clone(), save)),
+            Err(error) => {
+                warn!("could not load {}: {}", event.path, error);
+                None
+            }
+        }
+    }
+}
+
+pub fn handle_load(
+    mut commands: Commands,
+    mut requests: LoadRequests,
+    mut manager: ResMut<LevelManager>,
+    mut state: ResMut<GameState>,
+    mut rng: ResMut<GameRng>,
+    context: RestoreContext,
+    mut view: LoadedView
+) {
+    let Some((source, save)) = requests.next(&mut commands) else { return };
+
+    for level in manager.levels.iter_mut() {
+        level.tilemap.clear(&mut commands);
+    }
+    for entity in context.spawned_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let (restored, restored_state, restored_rng) = save.restore(&mut commands, &context.tilemap_factory.0, &context.bug_factory, context.asset_server.as_deref());
+    *manager = restored;
+    *state = restored_state;
+    *rng = restored_rng;
+
+    if let Some(transition) = view.transition.as_mut() {
+        transition.0 = None;
+    }
+    if let Ok((mut camera_transform, mut projection)) = view.camera_query.get_single_mut() {
+        let active = manager.active;
+        manager.switch_view(active, &mut camera_transform);
+        projection.scale = 1.0;
+    }
+    if let Some(tile_selection) = view.tile_selection.as_mut() {
+        tile_selection.tile = None;
+    }
+    view.selection_writer.send(SelectionEvent{deselected: None, selected: None});
+    view.loaded_writer.send(GameLoadedEvent);
+    info!("loaded {}", source);
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SaveSet;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .add_event::<GameLoadedEvent>()
+            .add_event::<SelectionEvent>()
+            .add_event::<WaveStateChange>()
+            .add_systems(Update, save_keys.run_if(resource_exists::<ButtonInput<KeyCode>>).in_set(SaveSet).in_set(GameSet::Input))
+            .add_systems(Update, (
+                handle_save.after(handle_continue_button),
+                handle_load.after(handle_save),
+            ).in_set(SaveSet).in_set(GameSet::Commands));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::fs;
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::bug::{BugKilledEvent, BugLeakedEvent};
+use crate::command::PlayerCommand;
+use crate::level::LevelManager;
+use crate::pipeline::GameSet;
+use crate::simulation::{simulation_app, SimulationConfig};
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH};
+use crate::tower::{tower_type_name, Balance, TowerType, TOWER_TYPES};
+use crate::wave::GameState;
+
+fn first_round() -> u32 { 1 }
+fn once() -> u32 { 1 }
+
+// built on the root level right before the given wave, in the order they are listed
+#[derive(Clone, Deserialize)]
+pub struct PlannedTower {
+    pub tower: TowerType,
+    pub position: (i32, i32),
+    #[serde(default = "first_round")]
+    pub round: u32
+}
+
+// every donation moves 100 Bit$ into the tower's level, same as the button
+#[derive(Clone, Deserialize)]
+pub struct PlannedDonation {
+    pub position: (i32, i32),
+    #[serde(default = "once")]
+    pub count: u32,
+    #[serde(default = "first_round")]
+    pub round: u32
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Parameter {
+    ResistorCost,
+    LedCost,
+    CapacitorCost,
+    LedDamage,
+    LedUpgradeDamage,
+    CapacitorDamage,
+    CapacitorUpgradeDamage
+}
+
+const PARAMETERS: [Parameter; 7] = [
+    Parameter::ResistorCost, Parameter::LedCost, Parameter::CapacitorCost, Parameter::LedDamage,
+    Parameter::LedUpgradeDamage, Parameter::CapacitorDamage, Parameter::CapacitorUpgradeDamage
+];
+
+impl Parameter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Parameter::ResistorCost => "resistor_cost",
+            Parameter::LedCost => "led_cost",
+            Parameter::CapacitorCost => "capacitor_cost",
+            Parameter::LedDamage => "led_damage",
+            Parameter::LedUpgradeDamage => "led_upgrade_damage",
+            Parameter::CapacitorDamage => "capacitor_damage",
+            Parameter::CapacitorUpgradeDamage => "capacitor_upgrade_damage"
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        PARAMETERS.iter().find(|parameter| parameter.name() == name).copied().ok_or_else(|| {
+            let names: Vec<&str> = PARAMETERS.iter().map(|parameter| parameter.name()).collect();
+            format!("unknown parameter {} (expected one of {})", name, names.join(", "))
+        })
+    }
+
+    pub fn apply(&self, balance: &mut Balance, value: i32) {
+        match self {
+            Parameter::ResistorCost => balance.resistor_cost = value.max(0) as u32,
+            Parameter::LedCost => balance.led_cost = value.max(0) as u32,
+            Parameter::CapacitorCost => balance.capacitor_cost = value.max(0) as u32,
+            Parameter::LedDamage => balance.led_damage = value,
+            Parameter::LedUpgradeDamage => balance.led_upgrade_damage = value,
+            Parameter::CapacitorDamage => balance.capacitor_damage = value,
+            Parameter::CapacitorUpgradeDamage => balance.capacitor_upgrade_damage = value
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Sweep {
+    pub parameter: Parameter,
+    pub from: i32,
+    pub to: i32,
+    pub step: i32
+}
+
+impl Sweep {
+    // PARAMETER=FROM:TO:STEP, for example capacitor_cost=50:150:25
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, range) = spec.split_once('=').ok_or_else(|| format!("invalid sweep {} (expected PARAMETER=FROM:TO:STEP)", spec))?;
+        let numbers: Vec<i32> = range.split(':').map(|number| number.parse().map_err(|_| format!("invalid number {}", number))).collect::<Result<_, _>>()?;
+        let [from, to, step] = numbers[..] else { return Err(format!("invalid range {} (expected FROM:TO:STEP)", range)) };
+        Ok(Self { parameter: Parameter::parse(name)?, from, to, step })
+    }
+
+    pub fn values(&self) -> Result<Vec<i32>, String> {
+        if self.step <= 0 || self.to < self.from {
+            return Err("a sweep needs FROM <= TO and a positive STEP".to_string());
+        }
+        Ok((self.from..=self.to).step_by(self.step as usize).collect())
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub seed: u64,
+    pub cable: Option<Vec<(i32, i32)>>,
+    pub money: i32,
+    pub health: i32,
+    pub rounds: u32,
+    pub towers: Vec<PlannedTower>,
+    pub donations: Vec<PlannedDonation>,
+    pub sweep: Option<Sweep>
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        let config = SimulationConfig::default();
+        Self {
+            seed: config.seed,
+            cable: None,
+            money: config.money,
+            health: config.health,
+            rounds: config.rounds,
+            towers: vec![],
+            donations: vec![],
+            sweep: None
+        }
+    }
+}
+
+impl Scenario {
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let scenario: Scenario = ron::from_str(&text).map_err(|error| error.to_string())?;
+        if let Some(cable) = &scenario.cable {
+            check_cable(cable)?;
+        }
+        Ok(scenario)
+    }
+}
+
+// set_cable panics on gaps, so a hand written cable is checked before it gets there
+fn check_cable(cable: &[(i32, i32)]) -> Result<(), String> {
+    if cable.len() < 2 {
+        return Err("a cable needs at least two tiles".to_string());
+    }
+    if let Some(tile) = cable.iter().find(|tile| !(-MAP_WIDTH / 2..MAP_WIDTH / 2).contains(&tile.0) || !(-MAP_HEIGHT / 2..MAP_HEIGHT / 2).contains(&tile.1)) {
+        return Err(format!("cable tile ({}, {}) is off the map", tile.0, tile.1));
+    }
+    for pair in cable.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if (a.0 - b.0).abs() + (a.1 - b.1).abs() != 1 {
+            return Err(format!("cable tiles ({}, {}) and ({}, {}) are not neighbours", a.0, a.1, b.0, b.1));
+        }
+    }
+    Ok(())
+}
+
+pub struct RoundReport {
+    pub round: u32,
+    pub health: i32,
+    pub money: i32,
+    // indexed like TOWER_TYPES
+    pub kills: [u32; 3],
+    pub leaks: u32
+}
+
+pub struct ScenarioReport {
+    pub rounds: Vec<RoundReport>,
+    pub skipped: Vec<String>
+}
+
+impl ScenarioReport {
+    pub fn rounds_survived(&self) -> u32 {
+        self.rounds.iter().filter(|round| round.health > 0).count() as u32
+    }
+
+    pub fn total_kills(&self) -> u32 {
+        self.rounds.iter().map(|round| round.kills.iter().sum::<u32>()).sum()
+    }
+
+    pub fn total_leaks(&self) -> u32 {
+        self.rounds.iter().map(|round| round.leaks).sum()
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "round     hp  money")?;
+        for tower_type in TOWER_TYPES.iter() {
+            write!(f, " {:>9}", tower_type_name(tower_type))?;
+        }
+        writeln!(f, "  leaks")?;
+        for round in self.rounds.iter() {
+            write!(f, "{:>5} {:>6} {:>6}", round.round, round.health, round.money)?;
+            for kills in round.kills.iter() {
+                write!(f, " {:>9}", kills)?;
+            }
+            writeln!(f, " {:>6}", round.leaks)?;
+        }
+        for skipped in self.skipped.iter() {
+            writeln!(f, "  {}", skipped)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource, Default)]
+struct RoundTally {
+    kills: [u32; 3],
+    leaks: u32
+}
+
+fn tally_round(
+    mut tally: ResMut<RoundTally>,
+    manager: Res<LevelManager>,
+    mut killed_reader: EventReader<BugKilledEvent>,
+    mut leaked_reader: EventReader<BugLeakedEvent>
+) {
+    let level = manager.get_current_level();
+    for event in killed_reader.read() {
+        let Some(tower) = event.tower.and_then(|position| level.towers.get(&position)) else { continue };
+        let Some(index) = TOWER_TYPES.iter().position(|tower_type| *tower_type == tower.tower_type) else { continue };
+        tally.kills[index] += 1;
+    }
+    tally.leaks += leaked_reader.read().count() as u32;
+}
+
+// plays the scenario on the root level only, one wave after another, with the given balance
+pub fn run_scenario(scenario: &Scenario, balance: Balance) -> ScenarioReport {
+    let config = SimulationConfig {
+        seed: scenario.seed,
+        money: scenario.money,
+        health: scenario.health,
+        cable: scenario.cable.clone(),
+        balance,
+        ..default()
+    };
+    let mut app = simulation_app(&config);
+    app.init_resource::<RoundTally>()
+        .add_systems(Update, tally_round.in_set(GameSet::Presentation));
+
+    let mut report = ScenarioReport {rounds: vec![], skipped: vec![]};
+    for round in 1..=scenario.rounds {
+        for planned in scenario.towers.iter().filter(|planned| planned.round == round) {
+            app.world.send_event(PlayerCommand::Build {tower: planned.tower, position: planned.position});
+            app.update();
+            let built = app.world.resource::<LevelManager>().levels[0].towers.get(&planned.position).is_some_and(|tower| tower.tower_type == planned.tower);
+            if !built {
+                report.skipped.push(format!("{} ({}, {}) not built before round {}", tower_type_name(&planned.tower), planned.position.0, planned.position.1, round));
+            }
+        }
+        for donation in scenario.donations.iter().filter(|donation| donation.round == round) {
+            if !app.world.resource::<LevelManager>().levels[0].towers.contains_key(&donation.position) {
+                report.skipped.push(format!("no tower at ({}, {}) to donate to before round {}", donation.position.0, donation.position.1, round));
+                continue;
+            }
+            for _ in 0..donation.count {
+                app.world.send_event(PlayerCommand::Donate {position: donation.position});
+                app.update();
+            }
+        }
+
+        *app.world.resource_mut::<RoundTally>() = RoundTally::default();
+        app.world.send_event(PlayerCommand::StartWave);
+        app.update();
+        loop {
+            let state = app.world.resource::<GameState>();
+            if !state.round_running || state.health <= 0 { break; }
+            app.update();
+        }
+
+        let health = app.world.resource::<GameState>().health;
+        let tally = app.world.resource::<RoundTally>();
+        report.rounds.push(RoundReport {
+            round,
+            health,
+            money: app.world.resource::<LevelManager>().levels[0].money,
+            kills: tally.kills,
+            leaks: tally.leaks
+        });
+        if health <= 0 { break; }
+    }
+    report
+}
\ No newline at end of file
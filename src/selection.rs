@@ -1,153 +1,131 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
-use crate::tilemap::TileType;
-use crate::level::LevelManager;
-use crate::tower::{tile_to_tower_types, tower_type_to_tile, tower_type_to_tile_type, TowerType, TOWER_TYPES};
-use crate::ui::{TowerInfo, DELETE_COORDS, RECURSE_COORDS, DONATE_COORDS, STEP_OUT_COORDS, CONTINUE_COORDS};
-use crate::wave::{GameState, WaveStateChange};
+use crate::level::{Level, LevelManager};
+use crate::tilemap::{MAP_HEIGHT, MAP_WIDTH};
+use crate::tower::TowerType;
+use crate::command::PlayerCommand;
+use crate::input::{Action, ActionState};
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::animations::{config_selection_animation, SelectionAnimationTimer};
+use crate::camera::transition_running;
+use crate::replay::replaying;
 
 #[derive(Resource)]
 pub struct TileSelection {
-    pub(crate) tile: Option<IVec3>
+    pub tile: Option<(i32, i32)>,
+    // shop option the player is looking at, the range preview and the tooltip follow it
+    pub shop: Option<TowerType>
+}
+
+// pointer for controllers, in tiles from the middle of the map so it keeps its place across levels.
+// None while the mouse is the pointer
+#[derive(Resource, Default)]
+pub struct GridCursor {
+    pub position: Option<Vec2>
+}
+
+impl GridCursor {
+    // floors like Tilemap::translation_to_grid, so a stick resting on an edge picks the same tile the mouse would
+    pub fn tile(&self) -> Option<(i32, i32)> {
+        self.position.map(|position| (position.x.floor() as i32, position.y.floor() as i32))
+    }
 }
 
 #[derive(Event)]
 pub struct SelectionEvent {
-    pub(crate) deselected: Option<IVec3>,
-    pub(crate) selected: Option<IVec3>
+    pub deselected: Option<(i32, i32)>,
+    pub selected: Option<(i32, i32)>
 }
 
 
 #[derive(Event)]
 pub struct TowerBuildEvent {
-    pub(crate) tower: TowerType,
-    pub(crate) position: (i32, i32)
+    pub tower: TowerType,
+    pub position: (i32, i32)
+}
+
+#[derive(Event)]
+pub struct TowerDeleteEvent {
+    pub position: (i32, i32)
+}
+
+#[derive(Event)]
+pub struct TowerDonateEvent {
+    pub position: (i32, i32)
 }
 
 
 #[derive(Event)]
 pub struct LevelSwitchEvent {
-    pub(crate) index: usize,
-    pub(crate) deselect: IVec3
+    pub index: usize
+}
+
+#[derive(SystemParam)]
+pub struct MousePointer<'w, 's> {
+    window_query: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    camera_query: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+    interaction_query: Query<'w, 's, &'static Interaction>
+}
+
+impl MousePointer<'_, '_> {
+    fn tile(&self, level: &Level) -> Option<(i32, i32)> {
+        let (camera, camera_transform) = self.camera_query.single();
+
+        let window = self.window_query.single();
+
+        let world_position = window.cursor_position().and_then(|cursor| camera.viewport_to_world(camera_transform, cursor)).map(|ray| ray.origin.truncate())?;
+        // the sidebar and the navigator handle their own clicks
+        if self.interaction_query.iter().any(|interaction| *interaction != Interaction::None) { return None };
+        Some(level.tilemap.translation_to_grid(world_position))
+    }
 }
 
 pub fn tile_selection(
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    camera_query: Query<(&Camera, &GlobalTransform)>,
-    mut manager: ResMut<LevelManager>,
-    buttons: Res<ButtonInput<MouseButton>>,
+    manager: Res<LevelManager>,
+    actions: Res<ActionState>,
+    grid_cursor: Res<GridCursor>,
+    mouse: MousePointer,
     mut tile_selection: ResMut<TileSelection>,
-    mut selection_event_writer: EventWriter<SelectionEvent>,
-    mut tower_build_event_writer: EventWriter<TowerBuildEvent>,
-    mut text_query: Query<&mut Text, With<TowerInfo>>,
-    mut commands: Commands,
-    mut level_switch_writer: EventWriter<LevelSwitchEvent>,
-    mut wave_state_writer: EventWriter<WaveStateChange>,
-    mut state: ResMut<GameState>
+    mut selection_event_writer: EventWriter<SelectionEvent>
 ) {
     // println!("[DEBUG] tile selection: {:?}", tile_selection.tile);
-    let (camera, camera_transform) = camera_query.single();
-
-    let window = window_query.single();
-
-    let world_position = window.cursor_position().and_then(|cursor| camera.viewport_to_world(camera_transform, cursor)).map(|ray| ray.origin.truncate());
-    if world_position.is_none() { return };
-    let mut level = manager.get_current_level_mut();
-    let hovered = level.tilemap.translation_to_grid(world_position.unwrap());
-    let tile = IVec3::new(hovered.0, hovered.1, 5);
-
-    let mut text = text_query.single_mut();
-    text.sections[0].value = "".to_string();
-    text.sections[1].value = "".to_string();
-    if tile_selection.tile.is_some() {
-        for tower_type in tile_to_tower_types(&level.tilemap, (tile_selection.tile.unwrap().x, tile_selection.tile.unwrap().y)) {
-            let tower_option_tile = tower_type_to_tile(&tower_type);
-            if hovered == tower_option_tile {
-                match tower_type {
-                    TowerType::Resistor => text.sections[0].value = format!("Slows bugs down\n\nRange:  Very low\nDamage: No\nSpeed:  Indefinite\nCost:   {} Bit$", level.resistor_cost()).to_string(),
-                    TowerType::Capacitor => text.sections[0].value = format!("Zaps bugs\n\nRange:  High\nDamage: High\nSpeed:  Low\nCost:   {} Bit$", level.capacitor_cost()).to_string(),
-                    // TowerType::Servo => {
-                    //     text.sections[0].value = "Suffocates bugs\n\nRange:  Line\nDamage: Low\nSpeed:  Indefinite\n\nNote:\n   no rotation!\n   (left only)".to_string();
-                    //     text.sections[1].value = "\n\n    (this is definitely a feature)".to_string();
-                    // },
-                    TowerType::Diode => text.sections[0].value = format!("Flashes bugs\n\nRange:  Low\nDamage: Medium\nSpeed:  Fast\nCost:   {} Bit$", level.led_cost()).to_string(),
-                }
-            }
-        }
+    if !actions.just_pressed(Action::Select) { return };
+    let level = manager.get_current_level();
+    let Some(tile) = grid_cursor.tile().or_else(|| mouse.tile(level)) else { return };
+    let previous = tile_selection.tile;
+
+    let on_map = (-MAP_WIDTH / 2..MAP_WIDTH / 2).contains(&tile.0) && (-MAP_HEIGHT / 2..MAP_HEIGHT / 2).contains(&tile.1);
+    if !on_map || tile_selection.tile == Some(tile) {
+        if previous.is_none() { return };
+        tile_selection.tile = None;
+        selection_event_writer.send(SelectionEvent{deselected: previous, selected: tile_selection.tile});
+        return;
     }
 
-    if buttons.just_pressed(MouseButton::Left) {
-        let previous = tile_selection.tile;
-        if tile.x > 7 {
-            if STEP_OUT_COORDS.contains(&hovered) && !state.round_running {
-                // println!("[DEBUG] parent");
-                if let Some(parent) = level.parent {
-                    // println!("AA");
-                    level_switch_writer.send(LevelSwitchEvent{index: parent, deselect: IVec3::new(0,0,2137)});
-                }
-            } else if CONTINUE_COORDS.contains(&hovered) && !state.round_running {
-                wave_state_writer.send(WaveStateChange{running: true});
-                state.round_running = true;
-            }
-            if previous.is_none() {
-                return;
-            }
-            let previous_tile = (previous.unwrap().x, previous.unwrap().y);
-            tile_selection.tile = None;
-            selection_event_writer.send(SelectionEvent{deselected: previous, selected: tile_selection.tile});
-
-            for tower_type in tile_to_tower_types(&level.tilemap, previous_tile) {
-                let tower_option_tile = tower_type_to_tile(&tower_type);
-                // println!("[DEBUG] tt: {:?}", tower_type);
-                if hovered == tower_option_tile {
-                    // println!("[DEBUG] pass");
-                    let cost = match tower_type {
-                        TowerType::Resistor => level.resistor_cost(),
-                        TowerType::Capacitor => level.capacitor_cost(),
-                        TowerType::Diode => level.led_cost()
-                    } as i32;
-                    if (level.money < cost) { return; }
-                    level.money -= cost;
-                    tower_build_event_writer.send(TowerBuildEvent{tower: tower_type, position: previous_tile});
-                    return;
-                }
-            }
-
-
-            if let Some(mut tower) = level.towers.get_mut(&previous_tile) {
-                if DELETE_COORDS.contains(&hovered) {
-                    level.tilemap.set(&mut commands, IVec3::new(previous_tile.0, previous_tile.1, 4), None);
-                    level.towers.remove(&previous_tile);
-                }
-                else if DONATE_COORDS.contains(&hovered) {
-                    level.money -= 100;
-                    tower.balance += 100;
-                    if level.money < 0 {
-                        tower.balance += level.money;
-                        level.money = 0;
-                    }
-                    let index = tower.level_index;
-                    manager.levels[index].money = tower.balance;
-                }
-                else if RECURSE_COORDS.contains(&hovered) && !state.round_running {
-                    let index = tower.level_index;
-                    level_switch_writer.send(LevelSwitchEvent { index, deselect: previous.unwrap() });
-                }
-            }
-
-            return;
-        }
-        // if tile.x < -7 { return; }
-
-        if tile_selection.tile == Some(tile) {
-            tile_selection.tile = None;
-            selection_event_writer.send(SelectionEvent{deselected: previous, selected: tile_selection.tile});
-            return;
-        }
-
-        tile_selection.tile = Some(tile);
-        selection_event_writer.send(SelectionEvent{deselected: previous, selected: tile_selection.tile});
-        // println!("[DEBUG] cursor position: {:?}", tile);
-        // level.tilemap.set(&mut commands, tile, Some(TileType::EndNorthCable))
+    tile_selection.tile = Some(tile);
+    selection_event_writer.send(SelectionEvent{deselected: previous, selected: tile_selection.tile});
+    // println!("[DEBUG] cursor position: {:?}", tile);
+    // level.tilemap.set(&mut commands, tile, Some(TileType::EndNorthCable))
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SelectionSet;
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.insert_resource(TileSelection{tile: None, shop: None})
+            .init_resource::<GridCursor>()
+            .insert_resource(SelectionAnimationTimer(Timer::from_seconds(0.5, TimerMode::Repeating)))
+            .add_event::<SelectionEvent>()
+            .add_event::<PlayerCommand>()
+            .add_systems(Update, tile_selection.run_if(not(transition_running)).run_if(not(replaying)).in_set(SelectionSet).in_set(GameSet::Input))
+            .add_systems(Update, (
+                config_selection_animation,
+                // selection_animation,
+            ).in_set(SelectionSet).in_set(GameSet::Presentation));
     }
 }
\ No newline at end of file
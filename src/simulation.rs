@@ -0,0 +1,224 @@
+use std::fmt;
+use std::time::Duration;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use crate::bug::{BugFactory, BugPlugin};
+use crate::level::{LevelManager, LevelPlugin, LevelTheme, StartingLevel, TilemapFactoryResource};
+use crate::cable::random_path;
+use crate::random::GameRng;
+use crate::command::{CommandPlugin, PlayerCommand};
+use crate::tilemap::TilemapFactory;
+use crate::tower::{tile_to_tower_types, tower_type_name, Balance, TowerPlugin, TowerType};
+use crate::wave::{GameState, WavePlugin};
+use crate::pipeline::GameSet;
+use crate::replay::{ReplayFile, ReplayPlayer, ReplayPlugin};
+use crate::autoplay::{Autoplay, AutoplayPlugin, AutoplayStrategy};
+use crate::save::SaveFile;
+use crate::eventlog::{EventLog, EventLogPlugin};
+
+pub struct SimulationConfig {
+    pub seed: u64,
+    pub money: i32,
+    pub health: i32,
+    // waves the root level has already been through before the first one is played
+    pub round: u32,
+    pub rounds: u32,
+    pub towers: Vec<(TowerType, (i32, i32))>,
+    // replaces the cable the seed would pick for the root level
+    pub cable: Option<Vec<(i32, i32)>>,
+    pub balance: Balance,
+    // starts from a saved game instead, seed, money, health and round are then taken from it
+    pub save: Option<SaveFile>
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self { seed: 2137, money: 300, health: 100, round: 0, rounds: 10, towers: vec![], cable: None, balance: Balance::default(), save: None }
+    }
+}
+
+pub struct TowerReport {
+    pub tower_type: TowerType,
+    pub position: (i32, i32),
+    pub kills: u32
+}
+
+pub struct SimulationReport {
+    pub rounds_survived: u32,
+    pub hp_lost: i32,
+    pub money_earned: i32,
+    pub towers: Vec<TowerReport>,
+    pub skipped_towers: Vec<(TowerType, (i32, i32))>,
+    pub ticks: u64
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rounds survived: {}", self.rounds_survived)?;
+        writeln!(f, "hp lost:         {}", self.hp_lost)?;
+        writeln!(f, "money earned:    {}", self.money_earned)?;
+        writeln!(f, "ticks:           {}", self.ticks)?;
+        for tower in self.towers.iter() {
+            writeln!(f, "  {:<10} ({:>3},{:>3})  kills: {}", tower_type_name(&tower.tower_type), tower.position.0, tower.position.1, tower.kills)?;
+        }
+        for (tower_type, position) in self.skipped_towers.iter() {
+            writeln!(f, "  {:<10} ({:>3},{:>3})  not built", tower_type_name(tower_type), position.0, position.1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub struct SimulationRounds(pub u32);
+
+// stands in for the player pressing Continue whenever the root level is idle
+pub fn continue_waves(
+    state: Res<GameState>,
+    manager: Res<LevelManager>,
+    rounds: Res<SimulationRounds>,
+    mut command_writer: EventWriter<PlayerCommand>
+) {
+    if state.round_running || manager.get_current_level().round >= rounds.0 { return; }
+    command_writer.send(PlayerCommand::StartWave);
+}
+
+pub fn simulation_app(config: &SimulationConfig) -> App {
+    let mut app = App::new();
+    // the runner only paces app.run(), like a served protocol game, simulations call app.update() as fast as they can
+    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0))))
+        .insert_resource(GameRng::new(config.seed))
+        .insert_resource(GameState {health: config.health, round_running: false})
+        .insert_resource(TilemapFactoryResource(TilemapFactory::headless()))
+        .insert_resource(SimulationRounds(0))
+        .insert_resource(StartingLevel {money: config.money, round: config.round})
+        .insert_resource(config.balance.clone())
+        .add_plugins((LevelPlugin, BugPlugin, TowerPlugin, WavePlugin, CommandPlugin))
+        .add_systems(Update, continue_waves.in_set(GameSet::Input));
+
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &app.world);
+    let mut manager = LevelManager {levels: vec![], active: 0};
+    let factory = TilemapFactory::headless();
+    let cable = config.cable.clone().unwrap_or_else(|| random_path(config.seed as usize));
+    manager.add_level(LevelTheme::Green, cable, &factory, &mut commands, None, None);
+    manager.get_current_level_mut().money = config.money;
+    manager.get_current_level_mut().round = config.round;
+    queue.apply(&mut app.world);
+    app.insert_resource(manager);
+    if let Some(save) = &config.save {
+        restore_save(&mut app, save.clone());
+    }
+    app
+}
+
+pub fn add_event_log(app: &mut App, event_log: Option<EventLog>) {
+    if let Some(log) = event_log {
+        app.insert_resource(log)
+            .add_plugins(EventLogPlugin);
+    }
+}
+
+fn restore_save(app: &mut App, save: SaveFile) {
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &app.world);
+    let (manager, state, rng) = save.restore(&mut commands, &TilemapFactory::headless(), &BugFactory::headless(), None);
+    queue.apply(&mut app.world);
+    app.insert_resource(manager)
+        .insert_resource(state)
+        .insert_resource(rng);
+}
+
+pub fn run_simulation(config: &SimulationConfig, event_log: Option<EventLog>) -> SimulationReport {
+    let mut app = simulation_app(config);
+    add_event_log(&mut app, event_log);
+    let mut skipped_towers = vec![];
+
+    // towers go through the same placement rules and prices as in the shop, one per update
+    for (tower_type, position) in config.towers.iter() {
+        let level = app.world.resource::<LevelManager>().get_current_level();
+        let cost = level.cost(tower_type, &config.balance) as i32;
+        if !tile_to_tower_types(&level.tilemap, *position).contains(tower_type) || level.money < cost {
+            skipped_towers.push((*tower_type, *position));
+            continue;
+        }
+        app.world.send_event(PlayerCommand::Build {tower: *tower_type, position: *position});
+        app.update();
+    }
+    let starting_money = app.world.resource::<LevelManager>().levels[0].money;
+    let starting_health = app.world.resource::<GameState>().health;
+    app.insert_resource(SimulationRounds(config.rounds));
+
+    let mut ticks = 0;
+    loop {
+        app.update();
+        ticks += 1;
+        let state = app.world.resource::<GameState>();
+        let round = app.world.resource::<LevelManager>().levels[0].round;
+        if state.health <= 0 { break; }
+        if !state.round_running && round >= config.rounds { break; }
+    }
+    report(&app, starting_health, starting_money, skipped_towers, ticks)
+}
+
+// plays a recorded game back from its seed until the last command has been applied and the wave after it is over
+pub fn run_replay(replay: ReplayFile, event_log: Option<EventLog>) -> SimulationReport {
+    let config = SimulationConfig {seed: replay.seed, money: replay.start.money, round: replay.start.round, health: replay.health, save: replay.save.clone(), ..default()};
+    let last_tick = replay.last_tick();
+    let mut app = simulation_app(&config);
+    add_event_log(&mut app, event_log);
+    let starting_money = app.world.resource::<LevelManager>().levels[0].money;
+    let starting_health = app.world.resource::<GameState>().health;
+    app.insert_resource(ReplayPlayer::new(replay))
+        .add_plugins(ReplayPlugin);
+
+    let mut ticks = 0;
+    loop {
+        app.update();
+        ticks += 1;
+        let state = app.world.resource::<GameState>();
+        if state.health <= 0 { break; }
+        if !state.round_running && ticks > last_tick { break; }
+    }
+    report(&app, starting_health, starting_money, vec![], ticks)
+}
+
+// lets the built-in bot play from the config's seed and money until it reaches its last round or dies
+pub fn run_autoplay(config: &SimulationConfig, strategy: AutoplayStrategy, event_log: Option<EventLog>) -> SimulationReport {
+    let rounds = strategy.rounds;
+    let mut app = simulation_app(config);
+    add_event_log(&mut app, event_log);
+    let starting_money = app.world.resource::<LevelManager>().levels[0].money;
+    let starting_health = app.world.resource::<GameState>().health;
+    app.insert_resource(Autoplay::new(true, strategy))
+        .add_plugins(AutoplayPlugin);
+
+    let mut ticks = 0;
+    loop {
+        app.update();
+        ticks += 1;
+        let state = app.world.resource::<GameState>();
+        let manager = app.world.resource::<LevelManager>();
+        if state.health <= 0 { break; }
+        if !state.round_running && manager.active == 0 && manager.levels[0].round >= rounds { break; }
+    }
+    report(&app, starting_health, starting_money, vec![], ticks)
+}
+
+fn report(app: &App, health: i32, starting_money: i32, skipped_towers: Vec<(TowerType, (i32, i32))>, ticks: u64) -> SimulationReport {
+    let state = app.world.resource::<GameState>();
+    let root = &app.world.resource::<LevelManager>().levels[0];
+    let towers: Vec<TowerReport> = root.towers.iter().map(|(position, tower)| TowerReport {
+        tower_type: tower.tower_type,
+        position: *position,
+        kills: tower.kills
+    }).collect();
+    SimulationReport {
+        rounds_survived: if state.health > 0 { root.round } else { root.round.saturating_sub(1) },
+        hp_lost: health - state.health,
+        money_earned: root.money - starting_money,
+        towers,
+        skipped_towers,
+        ticks
+    }
+}
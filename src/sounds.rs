@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 use bevy::audio::*;
+use crate::level::LevelManager;
+use crate::pipeline::{configure_pipeline, GameSet};
 
 #[derive(Component)]
 pub struct BugDeathSound;
@@ -7,31 +9,51 @@ pub struct BugDeathSound;
 #[derive(Component)]
 pub struct BackgroundMusic;
 
-pub fn setup_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // commands.spawn((
-    //     AudioBundle {
-    //         source: asset_server.load("sounds/bug_die.ogg"),
-    //         settings: PlaybackSettings {
-    //             paused: false,
-    //             mode: PlaybackMode::Despawn,
-    //             volume: Volume::new(1.0),
-    //             ..default()
-    //         },
-    //         ..default()
-    //     },
-    //     BugDeathSound{}
-    // ));
-
-    commands.spawn((
-        AudioBundle {
-            source: asset_server.load("sounds/music.ogg"),
-            settings: PlaybackSettings {
-                mode: PlaybackMode::Loop,
-                volume: Volume::new(0.2),
+pub fn update_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    manager: Res<LevelManager>,
+    music_query: Query<(Entity, Option<&AudioSink>), With<BackgroundMusic>>,
+    mut playing: Local<Option<&'static str>>
+) {
+    let (source, speed) = manager.get_current_level().theme.music();
+    if *playing != Some(source) {
+        for (entity, _) in music_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(source),
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Loop,
+                    volume: Volume::new(0.2),
+                    speed,
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        BackgroundMusic,
-    ));
+            BackgroundMusic,
+        ));
+        *playing = Some(source);
+        return;
+    }
+    for (_, sink) in music_query.iter() {
+        let Some(sink) = sink else { continue };
+        if sink.speed() != speed {
+            sink.set_speed(speed);
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AudioSet;
+
+// not to be confused with bevy's own AudioPlugin, this one only picks the music for the active level
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_systems(Update, update_music.in_set(AudioSet).in_set(GameSet::Presentation));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+use crate::bug::BugSprite;
+use crate::level::LevelManager;
+use crate::tilemap::TILE_SIZE;
+
+#[derive(Clone, Copy)]
+pub struct IndexedBug {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub cable_progress: usize
+}
+
+// bugs of the active level bucketed by the tile they are standing on, rebuilt every tick
+#[derive(Resource, Default)]
+pub struct BugIndex {
+    pub level: usize,
+    origin: Vec2,
+    cells: HashMap<(i32, i32), Vec<IndexedBug>>
+}
+
+impl BugIndex {
+    fn tile_of(&self, position: Vec2) -> (i32, i32) {
+        let tile = ((position - self.origin) / TILE_SIZE as f32).floor();
+        (tile.x as i32, tile.y as i32)
+    }
+
+    pub fn on_tile(&self, tile: (i32, i32)) -> &[IndexedBug] {
+        self.cells.get(&tile).map_or(&[], |bugs| bugs.as_slice())
+    }
+
+    pub fn in_radius(&self, center: Vec2, radius: f32) -> impl Iterator<Item = &IndexedBug> + '_ {
+        let center_tile = self.tile_of(center);
+        let reach = (radius / TILE_SIZE as f32).ceil() as i32;
+        (center_tile.0 - reach..=center_tile.0 + reach)
+            .flat_map(move |x| (center_tile.1 - reach..=center_tile.1 + reach).map(move |y| (x, y)))
+            .flat_map(|tile| self.on_tile(tile).iter())
+            .filter(move |bug| bug.position.distance(center) <= radius)
+    }
+
+    pub fn furthest_in_radius(&self, center: Vec2, radius: f32) -> Option<&IndexedBug> {
+        self.in_radius(center, radius).max_by_key(|bug| bug.cable_progress)
+    }
+}
+
+pub fn index_bugs(mut index: ResMut<BugIndex>, manager: Res<LevelManager>, bug_query: Query<(Entity, &Transform, &BugSprite)>) {
+    let level = manager.get_current_level();
+    index.level = manager.active;
+    index.origin = level.tilemap.grid_to_translation((0, 0)) - Vec2::splat(TILE_SIZE as f32 / 2.0);
+    for bugs in index.cells.values_mut() {
+        bugs.clear();
+    }
+    for (entity, transform, bug_sprite) in bug_query.iter() {
+        let position = transform.translation.truncate();
+        let tile = index.tile_of(position);
+        index.cells.entry(tile).or_default().push(IndexedBug {
+            entity,
+            position,
+            cable_progress: bug_sprite.cable_progress
+        });
+    }
+}
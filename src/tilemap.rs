@@ -6,7 +6,7 @@ pub const MAP_HEIGHT: i32 = 16;
 
 pub const TILE_SIZE: i32 = 48;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TileType {
     EndEastCable = 1,
     EndWestCable = 3,
@@ -56,17 +56,46 @@ pub enum TileType {
     HelpButton = 46,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TileLayer {
+    Cable = 1,
+    CableEnd = 3,
+    Tower = 4,
+    Selection = 5,
+    Preview = 6
+}
+
+impl TileLayer {
+    pub fn z(&self) -> f32 {
+        *self as i32 as f32
+    }
+}
+
+#[derive(Clone)]
+pub struct TileSet {
+    pub(crate) atlas_layout: Handle<TextureAtlasLayout>,
+    pub(crate) texture: Handle<Image>
+}
+
+// textures the tiles are drawn with, a headless tilemap only keeps track of the tile types
+#[derive(Clone)]
+pub struct TilemapVisuals {
+    pub(crate) tileset: TileSet
+}
+
+// the tile's sprite, if the map is drawn, and what the tile is
+type LayerTiles = HashMap<(i32, i32), (Option<Entity>, TileType)>;
+
 #[derive(Resource)]
 pub struct Tilemap {
-    tiles: HashMap<IVec3, Entity>,
-    atlas_layout: Handle<TextureAtlasLayout>,
-    texture: Handle<Image>,
+    tiles: HashMap<TileLayer, LayerTiles>,
+    visuals: Option<TilemapVisuals>,
     offset: Vec2
 }
 
 impl Tilemap {
-    pub fn new(atlas_layout: Handle<TextureAtlasLayout>, texture: Handle<Image>, offset: Vec2) -> Self {
-        Self { atlas_layout, texture, tiles: HashMap::new(), offset }
+    pub fn new(visuals: Option<TilemapVisuals>, offset: Vec2) -> Self {
+        Self { visuals, tiles: HashMap::new(), offset }
     }
 
     pub fn grid_to_translation(&self, position: (i32, i32)) -> Vec2 {
@@ -83,22 +112,38 @@ impl Tilemap {
         (x, y)
     }
 
-    pub fn set(&mut self, commands: &mut Commands, position: IVec3, tile_type: Option<TileType>) {
-        if let Some(entity) = self.tiles.get(&position) {
-            commands.entity(entity.clone()).despawn()
-        }
-        if tile_type.is_none() {
-            self.tiles.remove(&position);
+    pub fn set(&mut self, commands: &mut Commands, layer: TileLayer, position: (i32, i32), tile_type: Option<TileType>) {
+        let Some(tile_type) = tile_type else {
+            if let Some((Some(entity), _)) = self.tiles.get_mut(&layer).and_then(|tiles| tiles.remove(&position)) {
+                commands.entity(entity).despawn()
+            }
             return
         };
-
         let translation = Vec3::from(
-            (self.grid_to_translation((position.x, position.y)), position.z as f32)
+            (self.grid_to_translation(position), layer.z())
         );
+        let tiles = self.tiles.entry(layer).or_default();
+        let Some(visuals) = &self.visuals else {
+            tiles.insert(position, (None, tile_type));
+            return
+        };
+        let tileset = &visuals.tileset;
+
+        // an existing tile only gets its atlas index swapped, respawning it every toggle causes hitches
+        if let Some((Some(entity), current)) = tiles.get_mut(&position) {
+            if *current == tile_type { return; }
+            commands.entity(*entity).insert(TextureAtlas {
+                layout: tileset.atlas_layout.clone(),
+                index: tile_type as usize
+            });
+            *current = tile_type;
+            return
+        }
+
         let entity = commands.spawn((
             SpriteBundle {
                 transform: Transform::from_translation(translation),
-                texture: self.texture.clone(),
+                texture: tileset.texture.clone(),
                 sprite: Sprite {
                     custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
                     ..default()
@@ -106,26 +151,75 @@ impl Tilemap {
                 ..default()
             },
             TextureAtlas {
-                layout: self.atlas_layout.clone(),
-                index: tile_type.unwrap() as usize
+                layout: tileset.atlas_layout.clone(),
+                index: tile_type as usize
             }
         )
         ).id();
-        self.tiles.insert(position, entity);
+        tiles.insert(position, (Some(entity), tile_type));
     }
 
-    pub fn is_occupied(&self, position: IVec3) -> bool {
-        self.tiles.contains_key(&position)
+    pub fn set_many(&mut self, commands: &mut Commands, layer: TileLayer, tiles: impl IntoIterator<Item = ((i32, i32), Option<TileType>)>) {
+        for (position, tile_type) in tiles {
+            self.set(commands, layer, position, tile_type);
+        }
+    }
+
+    // recolours an already set tile, the tint survives atlas swaps
+    pub fn tint(&self, commands: &mut Commands, layer: TileLayer, position: (i32, i32), color: Color) {
+        let Some((Some(entity), _)) = self.tiles.get(&layer).and_then(|tiles| tiles.get(&position)) else { return };
+        commands.entity(*entity).insert(Sprite {
+            color,
+            custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+            ..default()
+        });
+    }
+
+    pub fn get(&self, layer: TileLayer, position: (i32, i32)) -> Option<TileType> {
+        self.tiles.get(&layer)?.get(&position).map(|(_, tile_type)| *tile_type)
+    }
+
+    pub fn clear_layer(&mut self, commands: &mut Commands, layer: TileLayer) {
+        for (_, (entity, _)) in self.tiles.remove(&layer).unwrap_or_default() {
+            if let Some(entity) = entity {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for (_, tiles) in self.tiles.drain() {
+            for (entity, _) in tiles.into_values() {
+                if let Some(entity) = entity {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+
+    pub fn iter_layer(&self, layer: TileLayer) -> impl Iterator<Item = ((i32, i32), TileType)> + '_ {
+        self.tiles.get(&layer).into_iter().flatten().map(|(position, (_, tile_type))| (*position, *tile_type))
+    }
+
+    pub fn is_occupied(&self, layer: TileLayer, position: (i32, i32)) -> bool {
+        self.get(layer, position).is_some()
     }
 }
 
 pub struct TilemapFactory {
-    pub(crate) atlas_layout: Handle<TextureAtlasLayout>,
-    pub(crate) texture: Handle<Image>
+    pub(crate) tilesets: HashMap<&'static str, TileSet>
 }
 
 impl TilemapFactory {
-    pub fn instantiate(&self, offset: Vec2) -> Tilemap {
-        Tilemap::new(self.atlas_layout.clone(), self.texture.clone(), offset)
+    pub fn headless() -> Self {
+        Self { tilesets: HashMap::new() }
+    }
+
+    pub fn instantiate(&self, offset: Vec2, tileset: &str) -> Tilemap {
+        // a headless factory has no tilesets loaded
+        let visuals = self.tilesets.get(tileset).map(|tileset| TilemapVisuals {
+            tileset: tileset.clone()
+        });
+        Tilemap::new(visuals, offset)
     }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
 use bevy::audio::{PlaybackMode, Volume};
-use bevy::math::IVec3;
 use bevy::prelude::*;
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
 use crate::bug::BugSprite;
 use crate::cable::random_path;
 use crate::level::{LevelManager, LevelTheme, TilemapFactoryResource};
-use crate::selection::TowerBuildEvent;
-use crate::tilemap::{TileType, Tilemap, TilemapFactory, TILE_SIZE};
+use crate::random::GameRng;
+use crate::selection::{TowerBuildEvent, TowerDeleteEvent, TowerDonateEvent};
+use crate::spatial::{index_bugs, BugIndex};
+use crate::animations::led_tower_animation;
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::tilemap::{TileLayer, TileType, Tilemap, TilemapFactory, TILE_SIZE};
 
-#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum TowerType {
     Resistor,
     Capacitor,
@@ -16,12 +19,29 @@ pub enum TowerType {
     Diode,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TowerSprite {
     pub tower_type: TowerType,
     pub frame_counter: u32,
     pub upgrade_factor: u32,
     pub balance: i32,
-    pub level_index: usize
+    pub level_index: usize,
+    pub durability: i32,
+    pub kills: u32
+}
+
+impl TowerSprite {
+    pub fn is_disabled(&self) -> bool {
+        self.durability <= 0
+    }
+}
+
+// an LED pulse that hit something or a capacitor shot, on the active level
+#[derive(Event)]
+pub struct TowerFiredEvent {
+    pub tower_type: TowerType,
+    pub position: (i32, i32),
+    pub targets: usize
 }
 
 pub const TOWER_TYPES: [TowerType; 3] = [TowerType::Resistor, TowerType::Capacitor, TowerType::Diode];
@@ -30,25 +50,72 @@ pub const RESISTOR_COST: u32 = 25;
 pub const LED_COST: u32 = 50;
 pub const CAPACITOR_COST: u32 = 75;
 
+// damage per hit, plus the upgrade damage for every upgrade factor of the tower
+pub const LED_DAMAGE: i32 = 250;
+pub const LED_UPGRADE_DAMAGE: i32 = 100;
+pub const CAPACITOR_DAMAGE: i32 = 750;
+pub const CAPACITOR_UPGRADE_DAMAGE: i32 = 150;
+
+// the numbers above as a resource, so the balance simulator can try other values
+#[derive(Resource, Clone)]
+pub struct Balance {
+    pub resistor_cost: u32,
+    pub led_cost: u32,
+    pub capacitor_cost: u32,
+    pub led_damage: i32,
+    pub led_upgrade_damage: i32,
+    pub capacitor_damage: i32,
+    pub capacitor_upgrade_damage: i32
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        Self {
+            resistor_cost: RESISTOR_COST,
+            led_cost: LED_COST,
+            capacitor_cost: CAPACITOR_COST,
+            led_damage: LED_DAMAGE,
+            led_upgrade_damage: LED_UPGRADE_DAMAGE,
+            capacitor_damage: CAPACITOR_DAMAGE,
+            capacitor_upgrade_damage: CAPACITOR_UPGRADE_DAMAGE
+        }
+    }
+}
+
+pub const TOWER_DURABILITY: i32 = 100;
+
 pub const DIODE_FRAMES: u32 = 60;
 pub const CAPACITOR_FRAMES: u32 = 240;
+
+// in tiles
+pub const LED_RANGE: f32 = 1.5;
+pub const CAPACITOR_RANGE: f32 = 6.0;
+
+// resistors have no range, they only act on the cable tile they sit on
+pub fn tower_range(tower_type: &TowerType) -> Option<f32> {
+    match tower_type {
+        TowerType::Resistor => None,
+        TowerType::Capacitor => Some(CAPACITOR_RANGE),
+        TowerType::Diode => Some(LED_RANGE),
+    }
+}
+
 pub fn tile_to_tower_types(tilemap: &Tilemap, position: (i32, i32)) -> Vec<TowerType> {
-    if tilemap.is_occupied(IVec3::new(position.0, position.1, 3)) || tilemap.is_occupied(IVec3::new(position.0, position.1, 4)) {
+    if tilemap.is_occupied(TileLayer::CableEnd, position) || tilemap.is_occupied(TileLayer::Tower, position) {
         return vec![];
     }
     // println!("[DEBUG] {} {}", position.0, position.1);
-    if tilemap.is_occupied(IVec3::new(position.0, position.1, 1)) {
+    if tilemap.is_occupied(TileLayer::Cable, position) {
         return vec![TowerType::Resistor];
     }
     vec![TowerType::Capacitor, TowerType::Diode]
 }
 
-pub fn tower_type_to_tile(tower_type: &TowerType) -> (i32, i32){
+pub fn tower_type_name(tower_type: &TowerType) -> &'static str {
     match tower_type {
-        TowerType::Resistor => (11, -1),
-        TowerType::Capacitor => (12, 0),
-        // TowerType::Servo => (12, 1),
-        TowerType::Diode => (11, 0)
+        TowerType::Resistor => "Resistor",
+        TowerType::Capacitor => "Capacitor",
+        TowerType::Diode => "LED"
     }
 }
 
@@ -67,14 +134,20 @@ pub fn handle_build_tower(
     mut tower_build_reader: EventReader<TowerBuildEvent>,
     mut manager: ResMut<LevelManager>,
     tilemap_factory: Res<TilemapFactoryResource>,
-    asset_server: Res<AssetServer>,
-    time: Res<Time>
+    asset_server: Option<Res<AssetServer>>,
+    mut rng: ResMut<GameRng>,
+    balance: Res<Balance>
 ) {
     for event in tower_build_reader.read() {
+        let level = manager.get_current_level_mut();
+        let cost = level.cost(&event.tower, &balance) as i32;
+        if !tile_to_tower_types(&level.tilemap, event.position).contains(&event.tower) || level.money < cost { continue; }
+        level.money -= cost;
+
         let parent = Some(manager.active.clone());
-        let duration_since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let random = duration_since_epoch.as_secs() as usize;
-        let recursed = manager.add_level(LevelTheme::Blue, random_path(random), &tilemap_factory.0, &mut commands, &asset_server, parent);
+        let random = rng.next_u64() as usize;
+        let theme = LevelTheme::for_depth(manager.depth(manager.active) + 1);
+        let recursed = manager.add_level(theme, random_path(random), &tilemap_factory.0, &mut commands, asset_server.as_deref(), parent);
         let mut level = manager.get_current_level_mut();
         match event.tower {
             TowerType::Resistor => level.resistor_count += 1,
@@ -82,8 +155,9 @@ pub fn handle_build_tower(
             TowerType::Diode => level.led_count += 1
         }
         // println!("[DEBUG] build event");
-        level.tilemap.set(&mut commands, IVec3::new(event.position.0, event.position.1, 4), Some(tower_type_to_tile_type(&event.tower)));
-        level.towers.insert(event.position, TowerSprite {tower_type: event.tower, frame_counter: 0, upgrade_factor: 1, balance: 0, level_index: recursed});
+        level.tilemap.set(&mut commands, TileLayer::Tower, event.position, Some(tower_type_to_tile_type(&event.tower)));
+        level.towers.insert(event.position, TowerSprite {tower_type: event.tower, frame_counter: 0, upgrade_factor: 1, balance: 0, level_index: recursed, durability: TOWER_DURABILITY, kills: 0});
+        let Some(asset_server) = &asset_server else { continue };
         commands.spawn((
             AudioBundle {
                 source: asset_server.load("sounds/place.ogg"),
@@ -99,109 +173,160 @@ pub fn handle_build_tower(
     }
 }
 
+pub fn handle_delete_tower(mut commands: Commands, mut tower_delete_reader: EventReader<TowerDeleteEvent>, mut manager: ResMut<LevelManager>) {
+    for event in tower_delete_reader.read() {
+        let level = manager.get_current_level_mut();
+        if level.towers.remove(&event.position).is_some() {
+            level.tilemap.set(&mut commands, TileLayer::Tower, event.position, None);
+        }
+    }
+}
+
+pub fn handle_donate(mut tower_donate_reader: EventReader<TowerDonateEvent>, mut manager: ResMut<LevelManager>) {
+    for event in tower_donate_reader.read() {
+        let level = manager.get_current_level_mut();
+        let Some(tower) = level.towers.get_mut(&event.position) else { continue };
+        level.money -= 100;
+        tower.balance += 100;
+        if level.money < 0 {
+            tower.balance += level.money;
+            level.money = 0;
+        }
+        let (index, balance) = (tower.level_index, tower.balance);
+        manager.levels[index].money = balance;
+    }
+}
 
-pub fn handle_resistor(mut bug_query: Query<(&Transform, &mut BugSprite)>, manager: Res<LevelManager>) {
+pub fn handle_resistor(mut bug_query: Query<&mut BugSprite>, manager: Res<LevelManager>, index: Res<BugIndex>) {
     let level = manager.get_current_level();
-    for (bug_transform, mut bug_sprite) in bug_query.iter_mut() {
-        let tower_sprite = level.towers.get(&level.tilemap.translation_to_grid(Vec2::new(bug_transform.translation.x, bug_transform.translation.y)));
+    for mut bug_sprite in bug_query.iter_mut() {
         bug_sprite.resistor_debuff = 1.0;
-        if tower_sprite.is_none() { continue; };
-        if tower_sprite.unwrap().tower_type != TowerType::Resistor { continue };
-        bug_sprite.resistor_debuff = 0.5 / tower_sprite.unwrap().upgrade_factor as f32;
+    }
+    for (position, tower) in level.towers.iter() {
+        if tower.tower_type != TowerType::Resistor { continue };
+        if tower.is_disabled() { continue };
+        for bug in index.on_tile(*position) {
+            let Ok(mut bug_sprite) = bug_query.get_mut(bug.entity) else { continue };
+            bug_sprite.resistor_debuff = 0.5 / tower.upgrade_factor as f32;
+        }
     }
 }
 
-pub fn handle_led(mut bug_query: Query<(&Transform, &mut BugSprite)>, mut manager: ResMut<LevelManager>) {
+pub fn handle_led(mut bug_query: Query<&mut BugSprite>, mut manager: ResMut<LevelManager>, index: Res<BugIndex>, balance: Res<Balance>, mut fired_writer: EventWriter<TowerFiredEvent>) {
     let mut level = manager.get_current_level_mut();
     for (position, mut tower) in level.towers.iter_mut() {
         if tower.tower_type != TowerType::Diode { continue };
+        if tower.is_disabled() { continue };
         tower.frame_counter = (tower.frame_counter + 1) % DIODE_FRAMES;
 
         let hurt = tower.frame_counter == 0;
         if hurt {
             // println!("[DEBUG] hurt");
-            for (bug_transform, mut bug_sprite) in bug_query.iter_mut() {
-                if level.tilemap.grid_to_translation(position.clone()).distance(Vec2::new(bug_transform.translation.x, bug_transform.translation.y)) > (TILE_SIZE as f32) * 1.5 {
-                    continue;
-                }
-                bug_sprite.health -= 250;
-                bug_sprite.health -= (tower.upgrade_factor * 100) as i32;
+            let mut targets = 0;
+            for bug in index.in_radius(level.tilemap.grid_to_translation(*position), (TILE_SIZE as f32) * LED_RANGE) {
+                let Ok(mut bug_sprite) = bug_query.get_mut(bug.entity) else { continue };
+                bug_sprite.health -= balance.led_damage;
+                bug_sprite.health -= tower.upgrade_factor as i32 * balance.led_upgrade_damage;
+                bug_sprite.last_hit = Some(*position);
+                targets += 1;
+            }
+            if targets > 0 {
+                fired_writer.send(TowerFiredEvent {tower_type: TowerType::Diode, position: *position, targets});
             }
         }
     }
 }
 
-#[derive(Component)]
-pub struct CapacitorTarget {
-    id: f32
-}
-
+// follows its bug around until it hits, and disappears with it
 #[derive(Component)]
 pub struct CapacitorBullet {
-    id: f32,
-    damage: i32
+    pub target: Entity,
+    damage: i32,
+    origin: (i32, i32)
 }
 
 pub fn handle_capacitor(
     mut commands: Commands,
-    mut bug_query: Query<(&Transform, Entity, &mut BugSprite)>,
     mut manager: ResMut<LevelManager>,
-    time: Res<Time>,
-    asset_server: Res<AssetServer>
+    bug_query: Query<(Entity, &Transform), With<BugSprite>>,
+    asset_server: Option<Res<AssetServer>>,
+    balance: Res<Balance>,
+    mut fired_writer: EventWriter<TowerFiredEvent>
 ) {
     let mut level = manager.get_current_level_mut();
     for (position, mut tower) in level.towers.iter_mut() {
         if tower.tower_type != TowerType::Capacitor { continue };
+        if tower.is_disabled() { continue };
         tower.frame_counter = (tower.frame_counter + 1) % CAPACITOR_FRAMES;
 
         let shoot = tower.frame_counter == 0;
         if shoot {
-            for (bug_transform, entity, mut bug_sprite) in bug_query.iter_mut() {
-                if level.tilemap.grid_to_translation(position.clone()).distance(Vec2::new(bug_transform.translation.x, bug_transform.translation.y)) > (TILE_SIZE as f32) * 6.0 {
-                    continue;
-                }
-                let target = commands.get_entity(entity);
-                if target.is_none() { continue; };
-                let id = time.elapsed_seconds();
-                // println!("[DEBUG] bullet id: {:?}", id);
-                target.unwrap().insert(CapacitorTarget{id});
-                commands.spawn((SpriteBundle {
-                    texture: asset_server.load("sprites/bullet.png"),
-                    transform: Transform::from_translation(Vec3::from((level.tilemap.grid_to_translation(position.clone()), 5.0))),
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::splat(TILE_SIZE as f32 / 2.0)),
+            // the first bug in range is the target, whichever one it is
+            let center = level.tilemap.grid_to_translation(*position);
+            let target = bug_query.iter().find(|(_, transform)| transform.translation.truncate().distance(center) <= (TILE_SIZE as f32) * CAPACITOR_RANGE);
+            if let Some((target, _)) = target {
+                let transform = Transform::from_translation(Vec3::from((level.tilemap.grid_to_translation(*position), 5.0)));
+                let bullet = CapacitorBullet{target, damage: balance.capacitor_damage + tower.upgrade_factor as i32 * balance.capacitor_upgrade_damage, origin: *position};
+                fired_writer.send(TowerFiredEvent {tower_type: TowerType::Capacitor, position: *position, targets: 1});
+                match &asset_server {
+                    Some(asset_server) => commands.spawn((SpriteBundle {
+                        texture: asset_server.load("sprites/bullet.png"),
+                        transform,
+                        sprite: Sprite {
+                            custom_size: Some(Vec2::splat(TILE_SIZE as f32 / 2.0)),
+                            ..default()
+                        },
                         ..default()
-                    },
-                    ..default()
-                }, CapacitorBullet{id, damage: 750 + (150 * tower.upgrade_factor) as i32}
-                ));
-                break;
+                    }, bullet)),
+                    None => commands.spawn((transform, bullet))
+                };
             }
         }
     }
 }
 const BULLET_SPEED: f32 = 21.37;
-pub fn handle_capacitor_bullet(mut commands: Commands, mut bullet_query: Query<(Entity, &mut Transform, &CapacitorBullet), Without<CapacitorTarget>>, mut target_query: Query<(&Transform, &mut BugSprite, &CapacitorTarget)>) {
+pub fn handle_capacitor_bullet(mut commands: Commands, mut bullet_query: Query<(Entity, &mut Transform, &CapacitorBullet), Without<BugSprite>>, mut target_query: Query<(&Transform, &mut BugSprite)>) {
     for (bullet_entity, mut bullet_transform, bullet_meta) in bullet_query.iter_mut() {
-        let mut found = false;
-        // println!("[DEBUG] bul. {:?}", bullet_meta.id);
-        for (target_transform, mut target_sprite, target_meta) in target_query.iter_mut() {
-            // println!("[DEBUG] tg. {:?}", target_meta.id);
-            if bullet_meta.id != target_meta.id { continue };
-            found = true;
-            if bullet_transform.translation.truncate().distance(target_transform.translation.truncate()) < TILE_SIZE as f32 * 0.2 {
-                // println!("[DEBUG] hit");
-                commands.entity(bullet_entity).despawn();
-                target_sprite.health -= bullet_meta.damage;
-                break;
-            }
-            let direction = target_transform.translation.truncate() - bullet_transform.translation.truncate();
-            let direction_normalized = direction.normalize() * BULLET_SPEED;
-            bullet_transform.translation += Vec3::from((direction_normalized, 0.0));
-            break;
-        }
-        if !found {
+        let Ok((target_transform, mut target_sprite)) = target_query.get_mut(bullet_meta.target) else {
+            commands.entity(bullet_entity).despawn();
+            continue;
+        };
+        if bullet_transform.translation.truncate().distance(target_transform.translation.truncate()) < TILE_SIZE as f32 * 0.2 {
             commands.entity(bullet_entity).despawn();
+            target_sprite.health -= bullet_meta.damage;
+            target_sprite.last_hit = Some(bullet_meta.origin);
+            continue;
         }
+        let direction = target_transform.translation.truncate() - bullet_transform.translation.truncate();
+        let direction_normalized = direction.normalize() * BULLET_SPEED;
+        bullet_transform.translation += Vec3::from((direction_normalized, 0.0));
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TowerSet;
+
+pub struct TowerPlugin;
+
+impl Plugin for TowerPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.init_resource::<Balance>()
+            .add_event::<TowerBuildEvent>()
+            .add_event::<TowerDeleteEvent>()
+            .add_event::<TowerDonateEvent>()
+            .add_event::<TowerFiredEvent>()
+            .add_systems(Update, (
+                handle_build_tower,
+                handle_delete_tower.after(handle_build_tower),
+                handle_donate.after(handle_delete_tower),
+            ).in_set(TowerSet).in_set(GameSet::Commands))
+            .add_systems(Update, (
+                handle_resistor.after(index_bugs),
+                handle_led.after(handle_resistor),
+                handle_capacitor.after(handle_led),
+                handle_capacitor_bullet.after(handle_capacitor),
+            ).in_set(TowerSet).in_set(GameSet::Simulation))
+            .add_systems(Update, led_tower_animation.in_set(TowerSet).in_set(GameSet::Presentation));
     }
 }
\ No newline at end of file
@@ -1,10 +1,16 @@
-use bevy::pbr::wireframe::Wireframe;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use crate::wave::GameState;
-use crate::level::{Level, LevelManager};
-use crate::selection::SelectionEvent;
-use crate::tilemap::{TileType, MAP_WIDTH, TILE_SIZE};
-use crate::tower::{tile_to_tower_types, tower_type_to_tile, tower_type_to_tile_type, TOWER_TYPES, TowerSprite};
+use crate::level::LevelManager;
+use crate::command::PlayerCommand;
+use crate::selection::{SelectionEvent, TileSelection};
+use crate::camera::transition_running;
+use crate::pipeline::{configure_pipeline, GameSet, GameSpeed};
+use crate::save::GameLoadedEvent;
+use crate::replay::replaying;
+use crate::navigator::{navigator_click, spawn_navigator, toggle_navigator, update_navigator};
+use crate::tilemap::TileType;
+use crate::tower::{tile_to_tower_types, tower_type_to_tile_type, Balance, TowerType, TOWER_TYPES, TowerSprite};
 #[derive(Component)]
 pub struct TowerInfo;
 
@@ -14,93 +20,162 @@ pub struct StatsText;
 #[derive(Component)]
 pub struct TowerStatistics;
 
+// sidebar and navigator text, drawn in the active level's theme colour
+#[derive(Component)]
+pub struct ThemedText;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SidebarAction {
+    Build(TowerType),
+    Sell,
+    StepInto,
+    Donate,
+    StepOut,
+    StartWave
+}
+
+// enabled is refreshed every frame from the game state, disabled buttons stay visible but ignore clicks
+#[derive(Component)]
+pub struct SidebarButton {
+    pub action: SidebarAction,
+    pub enabled: bool
+}
+
 pub const MENU_WIDTH: f32 = 368.0;
+const BUTTON_TILE_SIZE: f32 = 48.0;
+const BUTTONS_TILESET: &str = "tiles/TileSet3.png";
 
-pub fn spawn_text(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        TextBundle::from_section(
-            "",
-            TextStyle {
-                font: asset_server.load("fonts/QuinqueFive.ttf"),
-                font_size: 30.0,
-                color: Color::rgb(0., 1., 0.),
-                ..default()
-           },
-        )
-            .with_text_justify(JustifyText::Left)
-            .with_style(Style{
-                position_type: PositionType::Absolute,
-                top: Val::Px(10.0),
-                left: Val::Px((MAP_WIDTH * TILE_SIZE) as f32 + 20.0),
+pub const DELETE_TILES: [TileType; 4] = [TileType::Delete1, TileType::Delete2, TileType::Delete3, TileType::Delete4];
+pub const RECURSE_TILES: [TileType; 4] = [TileType::StepInto1, TileType::StepInto2, TileType::StepInto3, TileType::StepInto4];
+pub const DONATE_TILES: [TileType; 4] = [TileType::Donate1, TileType::Donate2, TileType::Donate3, TileType::Donate4];
+pub const STEP_OUT_TILES: [TileType; 4] = [TileType::StepOut1, TileType::StepOut2, TileType::StepOut3, TileType::StepOut4];
+pub const CONTINUE_TILES: [TileType; 4] = [TileType::Continue1, TileType::Continue2, TileType::Continue3, TileType::Continue4];
+
+fn spawn_button(parent: &mut ChildBuilder, action: SidebarAction, tiles: &[TileType], texture: &Handle<Image>, layout: &Handle<TextureAtlasLayout>) {
+    parent.spawn((
+        ButtonBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_self: AlignSelf::Start,
                 ..default()
-            }),
-        StatsText,
-    ));
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection {
-                value: "".to_string(),
-                style: TextStyle {
-                    font: asset_server.load("fonts/QuinqueFive.ttf"),
-                    font_size: 15.0,
-                    color: Color::rgb(0., 1., 0.),
-                    ..default()
-                },
             },
-            TextSection {
-                value: "".to_string(),
-                style: TextStyle {
-                    font: asset_server.load("fonts/QuinqueFive.ttf"),
-                    font_size: 7.5,
-                    color: Color::rgb(0., 1., 0.),
+            background_color: Color::NONE.into(),
+            ..default()
+        },
+        SidebarButton { action, enabled: true },
+    )).with_children(|button| {
+        for tile in tiles {
+            button.spawn(AtlasImageBundle {
+                style: Style {
+                    width: Val::Px(BUTTON_TILE_SIZE),
+                    height: Val::Px(BUTTON_TILE_SIZE),
                     ..default()
                 },
-            }
-        ])
-            .with_text_justify(JustifyText::Left)
-            .with_style(Style{
-                position_type: PositionType::Absolute,
-                top: Val::Px(500.0),
-                left: Val::Px((MAP_WIDTH * TILE_SIZE) as f32 + 20.0),
+                image: UiImage::new(texture.clone()),
+                texture_atlas: TextureAtlas { layout: layout.clone(), index: *tile as usize },
                 ..default()
-            }),
-        TowerInfo{},
-    ));
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection {
-                value: "".to_string(),
-                style: TextStyle {
-                    font: asset_server.load("fonts/QuinqueFive.ttf"),
-                    font_size: 20.0,
-                    color: Color::GREEN,
-                    ..default()
-                }
-            }
-        ]).with_text_justify(JustifyText::Left).with_style(
-            Style {
-                position_type: PositionType::Absolute,
-                top: Val::Px(300.0),
-                left: Val::Px((MAP_WIDTH * TILE_SIZE) as f32 + 20.0),
+            });
+        }
+    });
+}
+
+// the sidebar hangs off the right edge of the window, so nothing in it depends on where the map ends
+pub fn spawn_sidebar(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>) {
+    let font = asset_server.load("fonts/QuinqueFive.ttf");
+    let texture = asset_server.load(BUTTONS_TILESET);
+    let layout = texture_atlases.add(TextureAtlasLayout::from_grid(Vec2::splat(16.0), 8, 8, None, None));
+    let text_style = |font_size: f32| TextStyle {
+        font: font.clone(),
+        font_size,
+        color: Color::rgb(0., 1., 0.),
+    };
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(0.0),
+            right: Val::Px(0.0),
+            width: Val::Px(MENU_WIDTH),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(20.0)),
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+        ..default()
+    }).with_children(|sidebar| {
+        sidebar.spawn((
+            TextBundle::from_section("", text_style(30.0)).with_text_justify(JustifyText::Left),
+            StatsText,
+            ThemedText,
+        ));
+        sidebar.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                column_gap: Val::Px(12.0),
+                min_height: Val::Px(BUTTON_TILE_SIZE),
                 ..default()
+            },
+            ..default()
+        }).with_children(|shop| {
+            for tower_type in TOWER_TYPES {
+                spawn_button(shop, SidebarAction::Build(tower_type), &[tower_type_to_tile_type(&tower_type)], &texture, &layout);
             }
-        ), TowerStatistics{}
-    ));
-
+        });
+        sidebar.spawn((
+            TextBundle::from_sections([
+                TextSection::new("", text_style(15.0)),
+                TextSection::new("", text_style(7.5))
+            ]).with_text_justify(JustifyText::Left),
+            TowerInfo,
+            ThemedText,
+        ));
+        sidebar.spawn((
+            TextBundle::from_section("", text_style(20.0)).with_text_justify(JustifyText::Left),
+            TowerStatistics,
+            ThemedText,
+        ));
+        spawn_button(sidebar, SidebarAction::Sell, &DELETE_TILES, &texture, &layout);
+        spawn_button(sidebar, SidebarAction::StepInto, &RECURSE_TILES, &texture, &layout);
+        spawn_button(sidebar, SidebarAction::Donate, &DONATE_TILES, &texture, &layout);
+        // pushes the wave controls to the bottom
+        sidebar.spawn(NodeBundle {
+            style: Style { flex_grow: 1.0, ..default() },
+            ..default()
+        });
+        spawn_button(sidebar, SidebarAction::StepOut, &STEP_OUT_TILES, &texture, &layout);
+        spawn_button(sidebar, SidebarAction::StartWave, &CONTINUE_TILES, &texture, &layout);
+    });
 }
 
-pub fn update_stats_text(mut text_query: Query<&mut Text, With<StatsText>>, manager: Res<LevelManager>, state: Res<GameState>) {
+pub fn update_stats_text(mut text_query: Query<&mut Text, With<StatsText>>, manager: Res<LevelManager>, state: Res<GameState>, speed: Res<GameSpeed>) {
     let mut text = text_query.single_mut();
     let level = manager.get_current_level();
     text.sections[0].value = if level.parent.is_none() {
-        format!("Bit$: {}\nRnd:  {}/10\nHP:   {}", level.money.to_string(), level.round.to_string(), state.health)
+        format!("Bit$: {}\nRnd:  {}/10\nHP:   {}", level.money, level.round, state.health)
     } else {
-        format!("Bit$: {}\nRnd:  {}\nHP:   {}", level.money.to_string(), level.round.to_string(), state.health)
+        let durability = manager.owning_tower(manager.active).map_or(0, |tower| tower.durability);
+        format!("Bit$: {}\nRnd:  {}\nDur:  {}", level.money, level.round, durability)
+    };
+    if speed.paused {
+        text.sections[0].value.push_str("\nPAUSED");
+    } else if speed.ticks_per_frame > 1 {
+        text.sections[0].value.push_str(&format!("\nSpd:  x{}", speed.ticks_per_frame));
     }
     // let cap = if level.parent.is_none() { "/20" } else {""};
     // text.sections[0].value = format!("Bits: {}\nRnd:  {}{}\nHealth: {}", level.money.to_string(), level.round.to_string(), cap, state.health);
 }
 
+pub fn update_text_color(mut text_query: Query<&mut Text, With<ThemedText>>, manager: Res<LevelManager>) {
+    let color = manager.get_current_level().theme.text_color();
+    for mut text in text_query.iter_mut() {
+        if text.sections.iter().all(|section| section.style.color == color) { continue; }
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
 pub fn debug_add_money(
     mut level_manager: ResMut<LevelManager>,
     keys: Res<ButtonInput<KeyCode>>,
@@ -115,82 +190,145 @@ pub fn debug_add_money(
     }
 }
 
-pub fn tower_options(mut commands: Commands, mut selection_event_reader: EventReader<SelectionEvent>, mut manager: ResMut<LevelManager>) {
-    let mut level = manager.get_current_level_mut();
-    for event in selection_event_reader.read() {
-        // println!("[DEBUG] select: {:?} deselect: {:?}", event.selected, event.deselected);
-        for tower_type in TOWER_TYPES {
-            let tower_tile = tower_type_to_tile(&tower_type);
-            level.tilemap.set(&mut commands, IVec3::new(tower_tile.0, tower_tile.1, 0), None);
-        }
-        if event.selected.is_none() {
-            continue;
-        }
-        for tower_type in tile_to_tower_types(&level.tilemap, (event.selected.unwrap().x, event.selected.unwrap().y)) {
-            let tower_tile = tower_type_to_tile(&tower_type);
-            level.tilemap.set(&mut commands, IVec3::new(tower_tile.0, tower_tile.1, 0), Some(tower_type_to_tile_type(&tower_type)));
-        }
+fn tower_description(tower_type: &TowerType, cost: u32) -> String {
+    match tower_type {
+        TowerType::Resistor => format!("Slows bugs down\n\nRange:  Very low\nDamage: No\nSpeed:  Indefinite\nCost:   {} Bit$", cost),
+        TowerType::Capacitor => format!("Zaps bugs\n\nRange:  High\nDamage: High\nSpeed:  Low\nCost:   {} Bit$", cost),
+        // TowerType::Servo => {
+        //     text.sections[0].value = "Suffocates bugs\n\nRange:  Line\nDamage: Low\nSpeed:  Indefinite\n\nNote:\n   no rotation!\n   (left only)".to_string();
+        //     text.sections[1].value = "\n\n    (this is definitely a feature)".to_string();
+        // },
+        TowerType::Diode => format!("Flashes bugs\n\nRange:  Low\nDamage: Medium\nSpeed:  Fast\nCost:   {} Bit$", cost),
     }
 }
-fn show_control_panel(mut level: &mut Level, commands: &mut Commands, text: &mut Mut<Text>, tile_position: &(i32, i32), running: bool) {
-    level.tilemap.set(commands, IVec3::new(DELETE_COORDS[0].0, DELETE_COORDS[0].1, 10), Some(TileType::Delete1));
-    level.tilemap.set(commands, IVec3::new(DELETE_COORDS[1].0, DELETE_COORDS[1].1, 10), Some(TileType::Delete2));
-    level.tilemap.set(commands, IVec3::new(DELETE_COORDS[2].0, DELETE_COORDS[2].1, 10), Some(TileType::Delete3));
-    level.tilemap.set(commands, IVec3::new(DELETE_COORDS[3].0, DELETE_COORDS[3].1, 10), Some(TileType::Delete4));
-
-    if !running {
-        level.tilemap.set(commands, IVec3::new(RECURSE_COORDS[0].0, RECURSE_COORDS[0].1, 10), Some(TileType::StepInto1));
-        level.tilemap.set(commands, IVec3::new(RECURSE_COORDS[1].0, RECURSE_COORDS[1].1, 10), Some(TileType::StepInto2));
-        level.tilemap.set(commands, IVec3::new(RECURSE_COORDS[2].0, RECURSE_COORDS[2].1, 10), Some(TileType::StepInto3));
-        level.tilemap.set(commands, IVec3::new(RECURSE_COORDS[3].0, RECURSE_COORDS[3].1, 10), Some(TileType::StepInto4));
 
+fn tower_statistics(tower: &TowerSprite) -> String {
+    if tower.is_disabled() {
+        format!("Tower stats:\n\nBit$:    {}\nUpgrade: {}\nDur:     BROKEN", tower.balance, tower.upgrade_factor)
+    } else {
+        format!("Tower stats:\n\nBit$:    {}\nUpgrade: {}\nDur:     {}", tower.balance, tower.upgrade_factor, tower.durability)
     }
+}
+
+#[derive(SystemParam)]
+pub struct TowerTexts<'w, 's> {
+    info_query: Query<'w, 's, &'static mut Text, (With<TowerInfo>, Without<TowerStatistics>)>,
+    statistics_query: Query<'w, 's, &'static mut Text, (With<TowerStatistics>, Without<TowerInfo>)>
+}
 
-    level.tilemap.set(commands, IVec3::new(DONATE_COORDS[0].0, DONATE_COORDS[0].1, 10), Some(TileType::Donate1));
-    level.tilemap.set(commands, IVec3::new(DONATE_COORDS[1].0, DONATE_COORDS[1].1, 10), Some(TileType::Donate2));
-    level.tilemap.set(commands, IVec3::new(DONATE_COORDS[2].0, DONATE_COORDS[2].1, 10), Some(TileType::Donate3));
-    level.tilemap.set(commands, IVec3::new(DONATE_COORDS[3].0, DONATE_COORDS[3].1, 10), Some(TileType::Donate4));
+// which buttons are shown and which of them can be pressed, straight from the state instead of
+// respawning tiles whenever a tile gets selected or a level switched
+pub fn update_sidebar(
+    manager: Res<LevelManager>,
+    state: Res<GameState>,
+    selection: Res<TileSelection>,
+    balance: Res<Balance>,
+    mut button_query: Query<(&mut SidebarButton, &Interaction, &mut Style, &mut BackgroundColor, &Children)>,
+    mut image_query: Query<&mut BackgroundColor, (With<UiImage>, Without<SidebarButton>)>,
+    mut tower_texts: TowerTexts
+) {
+    let level = manager.get_current_level();
+    let selected_tower = selection.tile.and_then(|tile| level.towers.get(&tile));
+    let shop = selection.tile.map_or(vec![], |tile| tile_to_tower_types(&level.tilemap, tile));
 
+    for (mut button, interaction, mut style, mut background, children) in button_query.iter_mut() {
+        let (shown, enabled) = match button.action {
+            SidebarAction::Build(tower_type) => (shop.contains(&tower_type), level.money >= level.cost(&tower_type, &balance) as i32),
+            SidebarAction::Sell => (selected_tower.is_some(), true),
+            SidebarAction::StepInto => (selected_tower.is_some(), !state.round_running),
+            SidebarAction::Donate => (selected_tower.is_some(), level.money > 0),
+            SidebarAction::StepOut => (level.parent.is_some(), !state.round_running),
+            SidebarAction::StartWave => (true, !state.round_running)
+        };
+        let display = if shown { Display::Flex } else { Display::None };
+        if style.display != display {
+            style.display = display;
+        }
+        button.enabled = enabled;
 
-    let tower = level.towers.get(tile_position).unwrap();
-    text.sections[0].value = format!("Tower stats:\n\nBit$:    {}\nUpgrade: {}", tower.balance, tower.upgrade_factor).to_string();
-}
-fn hide_control_panel(mut level: &mut Level, commands: &mut Commands, text: &mut Mut<Text>) {
-    for delete_segment in DELETE_COORDS.iter().chain(DONATE_COORDS.iter()).chain(RECURSE_COORDS.iter()) {
-        level.tilemap.set(commands, IVec3::new(delete_segment.0, delete_segment.1, 10), None);
+        // a shop option picked from the gamepad looks hovered
+        let highlighted = matches!(button.action, SidebarAction::Build(tower_type) if selection.shop == Some(tower_type));
+        let interaction = if *interaction == Interaction::None && highlighted { Interaction::Hovered } else { *interaction };
+        let color = match (enabled, interaction) {
+            (false, _) | (true, Interaction::None) => Color::NONE,
+            (true, Interaction::Hovered) => Color::rgba(1., 1., 1., 0.15),
+            (true, Interaction::Pressed) => Color::rgba(1., 1., 1., 0.35)
+        };
+        if background.0 != color {
+            background.0 = color;
+        }
+        let tint = if enabled { Color::WHITE } else { Color::rgba(1., 1., 1., 0.3) };
+        for child in children.iter() {
+            // an image node's background colour tints the image
+            let Ok(mut image) = image_query.get_mut(*child) else { continue };
+            if image.0 != tint {
+                image.0 = tint;
+            }
+        }
     }
 
-    text.sections[0].value = "".to_string();
+    tower_texts.info_query.single_mut().sections[0].value = match selection.shop.filter(|tower_type| shop.contains(tower_type)) {
+        Some(tower_type) => tower_description(&tower_type, level.cost(&tower_type, &balance)),
+        None => "".to_string()
+    };
+    tower_texts.statistics_query.single_mut().sections[0].value = selected_tower.map_or("".to_string(), tower_statistics);
 }
-pub const DELETE_COORDS: [(i32, i32); 4] = [(10, -7), (11, -7), (12, -7), (13, -7)];
-pub const RECURSE_COORDS: [(i32, i32); 4] = [(10, -5), (11, -5), (12, -5), (13, -5)];
-pub const DONATE_COORDS: [(i32, i32); 4] = [(10, -3), (11, -3), (12, -3), (13, -3)];
-pub const STEP_OUT_COORDS: [(i32, i32); 4] = [(10, 2), (11, 2), (12, 2), (13, 2)];
-pub const CONTINUE_COORDS: [(i32, i32); 4] = [(10, 4), (11, 4), (12, 4), (13, 4)];
-
-
-pub fn tower_control_panel(
-    mut commands: Commands,
-    mut selection_event_reader: EventReader<SelectionEvent>,
-    mut manager: ResMut<LevelManager>,
-    mut tower_stats_query: Query<&mut Text, With<TowerStatistics>>,
-    state: Res<GameState>
+
+pub fn sidebar_interaction(
+    button_query: Query<(&Interaction, &SidebarButton), Changed<Interaction>>,
+    mut tile_selection: ResMut<TileSelection>,
+    mut selection_event_writer: EventWriter<SelectionEvent>,
+    mut command_writer: EventWriter<PlayerCommand>
 ) {
-    let mut tower_stats = tower_stats_query.get_single_mut().unwrap();
-
-    let mut level = manager.get_current_level_mut();
-    for event in selection_event_reader.read() {
-        // println!("level {:?}", level.money);
-        if event.selected.is_none() {
-            hide_control_panel(&mut level, &mut commands, &mut tower_stats);
-            continue;
+    for (interaction, button) in button_query.iter() {
+        if let SidebarAction::Build(tower_type) = button.action {
+            match interaction {
+                Interaction::None => if tile_selection.shop == Some(tower_type) { tile_selection.shop = None },
+                _ => tile_selection.shop = Some(tower_type)
+            }
+        }
+        if *interaction != Interaction::Pressed || !button.enabled { continue; }
+
+        let tile = tile_selection.tile;
+        let command = match button.action {
+            SidebarAction::Build(tower) => tile.map(|position| PlayerCommand::Build { tower, position }),
+            SidebarAction::Sell => tile.map(|position| PlayerCommand::Sell { position }),
+            SidebarAction::StepInto => tile.map(|position| PlayerCommand::StepInto { position }),
+            SidebarAction::Donate => tile.map(|position| PlayerCommand::Donate { position }),
+            SidebarAction::StepOut => Some(PlayerCommand::StepOut),
+            SidebarAction::StartWave => Some(PlayerCommand::StartWave)
+        };
+        // any press in the sidebar finishes with the selected tile
+        if tile.is_some() {
+            tile_selection.tile = None;
+            selection_event_writer.send(SelectionEvent { deselected: tile, selected: None });
         }
-        let tile_position = (event.selected.unwrap().x, event.selected.unwrap().y);
-        if level.towers.get(&tile_position).is_some() {
-            show_control_panel(&mut level, &mut commands, &mut tower_stats, &tile_position, state.round_running);
-        } else {
-            hide_control_panel(&mut level, &mut commands, &mut tower_stats);
-            continue;
+        if let Some(command) = command {
+            command_writer.send(command);
         }
     }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UiSet;
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        configure_pipeline(app);
+        app.add_event::<GameLoadedEvent>()
+            .add_systems(Startup, (spawn_sidebar, spawn_navigator))
+            .add_systems(Update, (
+                toggle_navigator,
+                navigator_click.run_if(not(transition_running)).run_if(not(replaying)),
+                sidebar_interaction.run_if(not(transition_running)).run_if(not(replaying)),
+            ).in_set(UiSet).in_set(GameSet::Input))
+            .add_systems(Update, (
+                update_stats_text,
+                update_text_color,
+                update_sidebar,
+                update_navigator,
+            ).in_set(UiSet).in_set(GameSet::Presentation));
+    }
 }
\ No newline at end of file
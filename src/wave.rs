@@ -4,10 +4,10 @@ use bevy::log::tracing_subscriber::fmt::writer::EitherWriter::B;
 use bevy::prelude::*;
 use bevy::tasks::futures_lite::StreamExt;
 use crate::bug::{BugFactory, BugSprite, BugType};
-use crate::level::{Level, LevelManager};
+use crate::level::{handle_level_switch, LevelManager};
 use crate::selection::LevelSwitchEvent;
-use crate::tilemap::TileType;
-use crate::ui::{CONTINUE_COORDS, STEP_OUT_COORDS};
+use crate::pipeline::{configure_pipeline, GameSet};
+use crate::tower::TOWER_DURABILITY;
 
 #[derive(Resource)]
 pub struct GameState {
@@ -20,8 +20,7 @@ pub struct WaveStateChange {
     pub running: bool
 }
 
-pub fn setup_game(mut commands: Commands, mut wave_state_writer: EventWriter<WaveStateChange>) {
-    commands.insert_resource(GameState {health: 100, round_running: false});
+pub fn setup_game(mut wave_state_writer: EventWriter<WaveStateChange>) {
     wave_state_writer.send(WaveStateChange{running: false});
 }
 
@@ -31,33 +30,6 @@ pub fn handle_loss(state: Res<GameState>, mut app_exit: ResMut<Events<bevy::app:
     }
 }
 
-pub fn show_continue_button(commands: &mut Commands, level: &mut Level) {
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[0].0, CONTINUE_COORDS[0].1, 10), Some(TileType::Continue1));
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[1].0, CONTINUE_COORDS[1].1, 10), Some(TileType::Continue2));
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[2].0, CONTINUE_COORDS[2].1, 10), Some(TileType::Continue3));
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[3].0, CONTINUE_COORDS[3].1, 10), Some(TileType::Continue4));
-    if level.parent.is_some() {
-
-        level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[0].0, STEP_OUT_COORDS[0].1, 10), Some(TileType::StepOut1));
-        level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[1].0, STEP_OUT_COORDS[1].1, 10), Some(TileType::StepOut2));
-        level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[2].0, STEP_OUT_COORDS[2].1, 10), Some(TileType::StepOut3));
-        level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[3].0, STEP_OUT_COORDS[3].1, 10), Some(TileType::StepOut4));
-    }
-}
-
-
-pub fn hide_continue_button(commands: &mut Commands, level: &mut Level) {
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[0].0, CONTINUE_COORDS[0].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[1].0, CONTINUE_COORDS[1].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[2].0, CONTINUE_COORDS[2].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(CONTINUE_COORDS[3].0, CONTINUE_COORDS[3].1, 10), None);
-
-    level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[0].0, STEP_OUT_COORDS[0].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[1].0, STEP_OUT_COORDS[1].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[2].0, STEP_OUT_COORDS[2].1, 10), None);
-    level.tilemap.set(commands, IVec3::new(STEP_OUT_COORDS[3].0, STEP_OUT_COORDS[3].1, 10), None);
-}
-
 pub fn spawn_wave(mut commands: Commands, mut manager: ResMut<LevelManager>, bug_factory: Res<BugFactory>, mut state: ResMut<GameState>, mut wave_state_writer: EventWriter<WaveStateChange>) {
     if !state.round_running { return; }
     let mut level = manager.get_current_level_mut();
@@ -75,24 +47,14 @@ pub fn spawn_wave(mut commands: Commands, mut manager: ResMut<LevelManager>, bug
             // wave_state_writer.send(WaveStateChange{running: false});
 
         }
-        Some(BugType::Bug) => {
-            let bug = bug_factory.instantiate_bugs(Transform::from_translation(translation));
-            commands.spawn(bug);
-        }
-        Some(BugType::Ant) => {
-            let bug = bug_factory.instantiate_ants(Transform::from_translation(translation));
-            commands.spawn(bug);
+        Some(bug_type) => {
+            bug_factory.spawn(&mut commands, &bug_type, Transform::from_translation(translation));
         }
-        Some(BugType::Hamster) => {
-            let bug = bug_factory.instantiate_hamster(Transform::from_translation(translation));
-            commands.spawn(bug);
-        }
-        _ => {}
     }
 
 }
 
-pub fn end_wave(mut commands: Commands, asset_server: Res<AssetServer>, bug_query: Query<(&BugSprite)>, mut state: ResMut<GameState>, mut wave_state_writer: EventWriter<WaveStateChange>, mut manager: ResMut<LevelManager>) {
+pub fn end_wave(mut commands: Commands, asset_server: Option<Res<AssetServer>>, bug_query: Query<&BugSprite>, mut state: ResMut<GameState>, mut wave_state_writer: EventWriter<WaveStateChange>, mut manager: ResMut<LevelManager>) {
     if !state.round_running { return; }
     let bugs = bug_query.iter().count();
     let mut level = manager.get_current_level_mut();
@@ -101,53 +63,44 @@ pub fn end_wave(mut commands: Commands, asset_server: Res<AssetServer>, bug_quer
     wave_state_writer.send(WaveStateChange{running: false});
     level.money += (350 * level.round) as i32;
     if level.parent.is_some() {
-        let parent = level.parent.unwrap();
         let round = level.round;
         let active = manager.active;
-        for (pos, mut tower) in manager.levels[parent].towers.iter_mut() {
-            if tower.level_index != active { continue; }
+        if let Some(tower) = manager.owning_tower_mut(active) {
             tower.upgrade_factor = round + 1;
+            tower.durability = TOWER_DURABILITY;
         }
     } else {
         if level.round == 10 {
-            commands.spawn((
-                AudioBundle {
-                    source: asset_server.load("sounds/win.ogg"),
-                    settings: PlaybackSettings {
-                        paused: false,
-                        mode: PlaybackMode::Despawn,
-                        volume: Volume::new(0.05),
+            if let Some(asset_server) = asset_server {
+                commands.spawn((
+                    AudioBundle {
+                        source: asset_server.load("sounds/win.ogg"),
+                        settings: PlaybackSettings {
+                            paused: false,
+                            mode: PlaybackMode::Despawn,
+                            volume: Volume::new(0.05),
+                            ..default()
+                        },
                         ..default()
                     },
-                    ..default()
-                },
-            ));
-
+                ));
+            }
         }
     }
 
 }
 
-pub fn handle_continue_button(
-    mut commands: Commands,
-    mut wave_state_reader: EventReader<WaveStateChange>, mut manager: ResMut<LevelManager>, mut level_switch_reader: EventReader<LevelSwitchEvent>, state: Res<GameState>) {
+pub fn handle_continue_button(mut wave_state_reader: EventReader<WaveStateChange>, mut manager: ResMut<LevelManager>, mut state: ResMut<GameState>) {
     let mut level = manager.get_current_level_mut();
     for event in wave_state_reader.read() {
         if event.running {
+            // a second press in the same frame must not skip a round
+            if state.round_running { continue; }
+            state.round_running = true;
             level.round += 1;
-            hide_continue_button(&mut commands, &mut level);
             let (queue, speed) = get_wave_composition(level.round);
             level.bug_queue = queue;
             level.bug_queue_speed = speed;
-        } else {
-            show_continue_button(&mut commands, &mut level);
-        }
-    }
-    for event in level_switch_reader.read() {
-        if state.round_running {
-            hide_continue_button(&mut commands, &mut level);
-        } else {
-            show_continue_button(&mut commands, &mut level);
         }
     }
 }
@@ -166,4 +119,27 @@ pub fn get_wave_composition(round: u32) -> (Vec<BugType>, u32) {
         10 => (vec![BugType::Hamster; 1], 1),
         _ => (vec![BugType::Hamster; (round - 9) as usize], 10)
     }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WaveSet;
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<GameState>() {
+            app.insert_resource(GameState {health: 100, round_running: false});
+        }
+        configure_pipeline(app);
+        app.add_event::<WaveStateChange>()
+            .add_event::<LevelSwitchEvent>()
+            .add_systems(Startup, setup_game)
+            .add_systems(Update, handle_continue_button.after(handle_level_switch).in_set(WaveSet).in_set(GameSet::Commands))
+            .add_systems(Update, (
+                spawn_wave,
+                end_wave.after(spawn_wave),
+                handle_loss.after(end_wave),
+            ).in_set(WaveSet).in_set(GameSet::Simulation));
+    }
 }
\ No newline at end of file
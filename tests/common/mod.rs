@@ -0,0 +1,171 @@
+// each test binary uses its own part of the harness
+#![allow(dead_code)]
+
+use bevy::app::AppExit;
+use bevy::input::keyboard::{Key, KeyboardInput, NativeKey};
+use bevy::input::{ButtonState, InputPlugin};
+use bevy::prelude::*;
+use step_into::bug::{BugSprite, BugType};
+use step_into::camera::CameraTransition;
+use step_into::command::PlayerCommand;
+use step_into::input::{InputMap, InputMapPlugin};
+use step_into::level::{Level, LevelManager};
+use step_into::selection::TileSelection;
+use step_into::simulation::{simulation_app, SimulationConfig};
+use step_into::tower::TowerType;
+use step_into::wave::GameState;
+
+// a straight cable across the middle of the map, (-8, 0) to (7, 0)
+pub fn straight_cable() -> Vec<(i32, i32)> {
+    (-8..=7).map(|x| (x, 0)).collect()
+}
+
+// what a test starts from, the root level is the only level
+pub struct TestLevel {
+    pub cable: Vec<(i32, i32)>,
+    pub health: i32,
+    pub money: i32,
+    pub round: u32,
+    pub towers: Vec<(TowerType, (i32, i32))>
+}
+
+impl Default for TestLevel {
+    fn default() -> Self {
+        Self { cable: straight_cable(), health: 100, money: 0, round: 0, towers: vec![] }
+    }
+}
+
+impl TestLevel {
+    pub fn build(self) -> TestGame {
+        let config = SimulationConfig {
+            cable: Some(self.cable),
+            health: self.health,
+            money: 1_000_000,
+            round: self.round,
+            ..default()
+        };
+        let mut game = TestGame {app: simulation_app(&config)};
+        // towers go through the regular build command, the money is only there to pay for them
+        for (tower, position) in self.towers {
+            game.app.world.send_event(PlayerCommand::Build {tower, position});
+            game.step(1);
+            assert!(game.level().towers.contains_key(&position), "could not build {:?} at {:?}", tower, position);
+        }
+        // building took a tick each, the towers start counting their frames when the test does
+        let mut manager = game.app.world.resource_mut::<LevelManager>();
+        let level = manager.get_current_level_mut();
+        level.money = self.money;
+        for tower in level.towers.values_mut() {
+            tower.frame_counter = 0;
+        }
+        game
+    }
+}
+
+pub struct TestGame {
+    pub app: App
+}
+
+impl TestGame {
+    pub fn level(&self) -> &Level {
+        self.app.world.resource::<LevelManager>().get_current_level()
+    }
+
+    pub fn health(&self) -> i32 {
+        self.app.world.resource::<GameState>().health
+    }
+
+    pub fn money(&self) -> i32 {
+        self.level().money
+    }
+
+    pub fn round_running(&self) -> bool {
+        self.app.world.resource::<GameState>().round_running
+    }
+
+    // at the start of the cable, walking it like a bug from a wave
+    pub fn spawn_bug(&mut self, bug_type: BugType) -> Entity {
+        let start = self.level().cable()[0];
+        self.spawn_bug_with(bug_type, start, |_| {})
+    }
+
+    // on any cable tile, with its stats adjusted first (a speed_factor of 0 keeps it in place)
+    pub fn spawn_bug_with(&mut self, bug_type: BugType, tile: (i32, i32), adjust: impl FnOnce(&mut BugSprite)) -> Entity {
+        let level = self.level();
+        let progress = level.cable().iter().position(|cable_tile| *cable_tile == tile).expect("bugs can only be placed on the cable");
+        let translation = Vec3::from((level.grid_to_translation(tile), 2.0));
+        let mut sprite = bug_type.stats();
+        sprite.cable_progress = progress;
+        adjust(&mut sprite);
+
+        // the same components the headless BugFactory spawns
+        self.app.world.spawn((Transform::from_translation(translation), sprite)).id()
+    }
+
+    pub fn bug_count(&mut self) -> usize {
+        self.app.world.query::<&BugSprite>().iter(&self.app.world).count()
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.app.world.get_entity(entity).is_some()
+    }
+
+    pub fn step(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.update();
+        }
+    }
+
+    // steps until the condition holds and returns how many ticks that took
+    pub fn step_until(&mut self, max_ticks: u32, mut condition: impl FnMut(&mut TestGame) -> bool) -> Option<u32> {
+        for tick in 1..=max_ticks {
+            self.app.update();
+            if condition(self) { return Some(tick); }
+        }
+        None
+    }
+
+    pub fn start_wave(&mut self) {
+        self.app.world.send_event(PlayerCommand::StartWave);
+        self.step(1);
+    }
+
+    // clears the field and the rest of the wave, so the wave ends on the next tick
+    pub fn kill_all_bugs(&mut self) {
+        let bugs: Vec<Entity> = self.app.world.query_filtered::<Entity, With<BugSprite>>().iter(&self.app.world).collect();
+        for bug in bugs {
+            self.app.world.despawn(bug);
+        }
+        self.app.world.resource_mut::<LevelManager>().get_current_level_mut().bug_queue.clear();
+    }
+
+    // keyboard actions with the default bindings, the keys go through bevy's input like real key presses
+    pub fn with_input(mut self) -> Self {
+        self.app.add_plugins(InputPlugin)
+            .insert_resource(CameraTransition(None))
+            .insert_resource(TileSelection {tile: None, shop: None})
+            .insert_resource(InputMap::default())
+            .add_plugins(InputMapPlugin);
+        self
+    }
+
+    pub fn select(&mut self, tile: (i32, i32)) {
+        self.app.world.resource_mut::<TileSelection>().tile = Some(tile);
+    }
+
+    pub fn selection(&self) -> &TileSelection {
+        self.app.world.resource::<TileSelection>()
+    }
+
+    // pressed and released again within one frame
+    pub fn tap(&mut self, key_code: KeyCode) {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            self.app.world.send_event(KeyboardInput {key_code, logical_key: Key::Unidentified(NativeKey::Unidentified), state, window: Entity::PLACEHOLDER});
+        }
+        self.step(1);
+    }
+
+    pub fn exit_requested(&self) -> bool {
+        !self.app.world.resource::<Events<AppExit>>().is_empty()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+mod common;
+
+use common::TestLevel;
+use step_into::autoplay::AutoplayStrategy;
+use step_into::bug::BugType;
+use step_into::simulation::{run_autoplay, SimulationConfig};
+use step_into::tower::{TowerType, DIODE_FRAMES, LED_DAMAGE, LED_UPGRADE_DAMAGE};
+
+// long enough for any bug to walk the 16 tile cable
+const WALK_TICKS: u32 = 2000;
+
+#[test]
+fn bug_walking_the_whole_cable_costs_10_hp() {
+    let mut game = TestLevel::default().build();
+    game.spawn_bug(BugType::Bug);
+
+    assert!(game.step_until(WALK_TICKS, |game| game.bug_count() == 0).is_some(), "the bug never left the cable");
+    assert_eq!(game.health(), 90);
+}
+
+#[test]
+fn hamster_leak_costs_2147_hp() {
+    let mut game = TestLevel {health: 5000, ..Default::default()}.build();
+    game.spawn_bug(BugType::Hamster);
+
+    assert!(game.step_until(WALK_TICKS * 3, |game| game.bug_count() == 0).is_some(), "the hamster never left the cable");
+    assert_eq!(game.health(), 5000 - 2147);
+}
+
+#[test]
+fn led_kills_a_bug_in_expected_ticks() {
+    let mut game = TestLevel {towers: vec![(TowerType::Diode, (0, 1))], ..Default::default()}.build();
+    let bug = game.spawn_bug_with(BugType::Bug, (0, 0), |sprite| sprite.speed_factor = 0.0);
+
+    // a fresh LED of upgrade factor 1 flashes every DIODE_FRAMES ticks
+    let damage = LED_DAMAGE + LED_UPGRADE_DAMAGE;
+    let hits = (BugType::Bug.stats().health + damage - 1) / damage;
+    let ticks = hits as u32 * DIODE_FRAMES;
+
+    game.step(ticks - 1);
+    assert!(game.is_alive(bug), "the bug died before the LED flashed {} times", hits);
+    game.step(1);
+    assert!(!game.is_alive(bug), "the bug survived {} flashes", hits);
+    assert_eq!(game.level().towers[&(0, 1)].kills, 1);
+    assert_eq!(game.health(), 100);
+}
+
+#[test]
+fn end_wave_pays_350_per_round() {
+    for round in [1, 5] {
+        let mut game = TestLevel {money: 100, round: round - 1, ..Default::default()}.build();
+        game.start_wave();
+        assert!(game.round_running());
+        assert_eq!(game.level().round, round);
+
+        game.kill_all_bugs();
+        game.step(1);
+        assert!(!game.round_running());
+        assert_eq!(game.money(), 100 + 350 * round as i32);
+    }
+}
+
+#[test]
+fn handle_loss_exits_at_0_hp() {
+    let mut game = TestLevel {health: 20, ..Default::default()}.build();
+    game.spawn_bug(BugType::Bug);
+    game.step_until(WALK_TICKS, |game| game.bug_count() == 0);
+    assert_eq!(game.health(), 10);
+    assert!(!game.exit_requested());
+
+    game.spawn_bug(BugType::Bug);
+    game.step_until(WALK_TICKS, |game| game.bug_count() == 0);
+    assert_eq!(game.health(), 0);
+    assert!(game.exit_requested());
+}
+
+#[test]
+fn autoplay_beats_the_first_10_rounds() {
+    let config = SimulationConfig {seed: 2137, ..Default::default()};
+    let report = run_autoplay(&config, AutoplayStrategy::default(), None);
+    assert!(report.rounds_survived >= 10, "the bot only survived {} rounds", report.rounds_survived);
+    assert!(report.hp_lost < config.health, "the bot lost all its health");
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+mod common;
+
+use std::fs;
+use bevy::prelude::*;
+use common::TestLevel;
+use step_into::input::{Action, Binding, InputMap};
+use step_into::pipeline::GameSpeed;
+use step_into::selection::GridCursor;
+use step_into::tower::TowerType;
+
+fn write_settings(name: &str, text: &str) -> String {
+    let path = std::env::temp_dir().join(format!("step-into-{}-{}.ron", name, std::process::id()));
+    fs::write(&path, text).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn bindings_parse_from_their_names() {
+    assert_eq!(Binding::parse("KeyA"), Some(Binding::Key(KeyCode::KeyA)));
+    assert_eq!(Binding::parse("ArrowUp"), Some(Binding::Key(KeyCode::ArrowUp)));
+    assert_eq!(Binding::parse("MouseLeft"), Some(Binding::Mouse(MouseButton::Left)));
+    assert_eq!(Binding::parse("GamepadSouth"), Some(Binding::Gamepad(GamepadButtonType::South)));
+    assert_eq!(Binding::parse("Foo"), None);
+    assert_eq!(Binding::parse("GamepadFoo"), None);
+    assert_eq!(Binding::parse("keya"), None);
+}
+
+#[test]
+fn input_map_keeps_defaults_for_omitted_actions() {
+    let path = write_settings("partial", "(bindings: {Donate: [\"KeyG\", \"GamepadNorth\"], Sell: []})");
+    let map = InputMap::read(&path).unwrap();
+    let defaults = InputMap::default();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(map.bindings[&Action::Donate], vec![Binding::Key(KeyCode::KeyG), Binding::Gamepad(GamepadButtonType::North)]);
+    // an empty list unbinds the action instead of falling back to its defaults
+    assert!(map.bindings[&Action::Sell].is_empty());
+    assert_eq!(map.bindings[&Action::StartWave], defaults.bindings[&Action::StartWave]);
+    assert_eq!(map.bindings[&Action::Select], defaults.bindings[&Action::Select]);
+}
+
+#[test]
+fn input_map_refuses_unknown_keys() {
+    let path = write_settings("unknown", "(bindings: {BuildLed: [\"Foo\"]})");
+    let result = InputMap::read(&path);
+    fs::remove_file(&path).unwrap();
+
+    assert!(result.is_err_and(|error| error.contains("Foo")));
+}
+
+#[test]
+fn donate_is_sent_once_when_fast_forwarding() {
+    let mut game = TestLevel {towers: vec![(TowerType::Diode, (0, 1))], money: 1000, ..Default::default()}.build().with_input();
+    game.app.world.resource_mut::<GameSpeed>().ticks_per_frame = 4;
+    game.select((0, 1));
+
+    game.tap(KeyCode::KeyD);
+    game.step(2);
+    assert_eq!(game.money(), 900);
+    assert_eq!(game.level().towers[&(0, 1)].balance, 100);
+}
+
+#[test]
+fn shop_cycles_through_the_tile_options_and_builds_the_highlighted_one() {
+    let mut game = TestLevel {money: 1000, ..Default::default()}.build().with_input();
+    // off the cable the shop offers a Capacitor, then an LED
+    let tile = (0, 3);
+    game.select(tile);
+
+    // nothing highlighted yet, stepping back starts from the last option
+    game.tap(KeyCode::BracketLeft);
+    assert_eq!(game.selection().shop, Some(TowerType::Diode));
+    game.tap(KeyCode::BracketRight);
+    assert_eq!(game.selection().shop, Some(TowerType::Capacitor));
+    game.tap(KeyCode::BracketLeft);
+    assert_eq!(game.selection().shop, Some(TowerType::Diode));
+    game.tap(KeyCode::BracketRight);
+    assert_eq!(game.selection().shop, Some(TowerType::Capacitor));
+
+    game.tap(KeyCode::Enter);
+    game.step(1);
+    assert_eq!(game.level().towers.get(&tile).map(|tower| tower.tower_type), Some(TowerType::Capacitor));
+}
+
+#[test]
+fn shop_on_the_cable_only_offers_the_resistor() {
+    let mut game = TestLevel {money: 1000, ..Default::default()}.build().with_input();
+    game.select((0, 0));
+
+    game.tap(KeyCode::BracketRight);
+    game.tap(KeyCode::BracketRight);
+    assert_eq!(game.selection().shop, Some(TowerType::Resistor));
+
+    game.tap(KeyCode::Enter);
+    game.step(1);
+    assert_eq!(game.level().towers.get(&(0, 0)).map(|tower| tower.tower_type), Some(TowerType::Resistor));
+}
+
+#[test]
+fn arrow_keys_take_the_grid_cursor_along() {
+    let mut game = TestLevel::default().build().with_input();
+    game.app.insert_resource(GridCursor {position: Some(Vec2::new(0.5, 0.5))});
+    game.select((0, 0));
+
+    game.tap(KeyCode::ArrowUp);
+    assert_eq!(game.selection().tile, Some((0, 1)));
+    assert_eq!(game.app.world.resource::<GridCursor>().tile(), Some((0, 1)));
+}
\ No newline at end of file
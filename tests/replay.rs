@@ -0,0 +1,70 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestLevel;
+use step_into::bug::BugSprite;
+use step_into::command::PlayerCommand;
+use step_into::level::LevelManager;
+use step_into::random::GameRng;
+use step_into::replay::{ReplayFile, ReplayPlayer, ReplayPlugin, ReplayRecorder};
+use step_into::save::{LoadGameEvent, SaveFile, SaveGameEvent, SavePlugin};
+use step_into::simulation::{simulation_app, SimulationConfig};
+use step_into::tower::TowerType;
+use step_into::wave::GameState;
+
+// how long the game goes on from the tick of the load, long enough for the wave to reach the towers
+const PLAYED_TICKS: u32 = 302;
+
+fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("step-into-{}-{}.ron", name, std::process::id())).to_string_lossy().into_owned()
+}
+
+// the whole game state as a save would keep it
+fn snapshot(app: &mut App) -> String {
+    let bugs: Vec<(Transform, BugSprite)> = app.world.query::<(&Transform, &BugSprite)>().iter(&app.world).map(|(transform, sprite)| (*transform, sprite.clone())).collect();
+    let world = &app.world;
+    let save = SaveFile::capture(world.resource::<LevelManager>(), world.resource::<GameState>(), world.resource::<GameRng>(), bugs.iter().map(|(transform, sprite)| (transform, sprite)));
+    ron::to_string(&save).unwrap()
+}
+
+#[test]
+fn replay_recorded_after_a_load_starts_from_the_loaded_game() {
+    let save_path = temp_path("replay-save");
+    let replay_path = temp_path("replay");
+    let mut game = TestLevel {money: 1000, ..Default::default()}.build();
+    game.app.add_plugins((SavePlugin, ReplayPlugin));
+    game.step(1);
+    game.app.world.resource_mut::<ReplayRecorder>().path = replay_path.clone();
+
+    game.app.world.send_event(PlayerCommand::Build {tower: TowerType::Capacitor, position: (0, 1)});
+    game.step(1);
+    game.app.world.send_event(SaveGameEvent {path: save_path.clone()});
+    game.step(1);
+    // played on after the save, none of this is in the loaded game
+    game.app.world.send_event(PlayerCommand::Build {tower: TowerType::Capacitor, position: (2, 1)});
+    game.start_wave();
+    game.step(100);
+
+    game.app.world.send_event(LoadGameEvent {path: save_path.clone()});
+    game.step(1);
+    game.app.world.send_event(PlayerCommand::Build {tower: TowerType::Capacitor, position: (-3, 1)});
+    game.start_wave();
+    game.step(PLAYED_TICKS - 2);
+    let recorded = snapshot(&mut game.app);
+
+    let replay = ReplayFile::read(&replay_path).unwrap();
+    std::fs::remove_file(&save_path).unwrap();
+    std::fs::remove_file(&replay_path).unwrap();
+    assert!(replay.save.is_some());
+    assert_eq!(replay.commands.len(), 2);
+
+    // the tick of the load is the replay's first
+    let config = SimulationConfig {save: replay.save.clone(), ..Default::default()};
+    let mut app = simulation_app(&config);
+    app.insert_resource(ReplayPlayer::new(replay))
+        .add_plugins(ReplayPlugin);
+    for _ in 0..PLAYED_TICKS {
+        app.update();
+    }
+    assert_eq!(snapshot(&mut app), recorded);
+}
\ No newline at end of file